@@ -0,0 +1,58 @@
+//! Time `merge_touching` on synthetic models of 10 to 5000 elements, both
+//! sparse and dense ones where nearly everything ends up merged. Run
+//! with `cargo bench`. That it merges like the original algorithm is
+//! checked by the tests in merging.rs.
+
+#[macro_use]
+extern crate serde_derive;
+
+// Without the test harness its tests aren't compiled, which leaves their
+// imports unused.
+#[path = "../src/merging.rs"]
+#[allow(dead_code, unused_imports)]
+mod merging;
+
+use std::time::Instant;
+
+#[derive(Clone)]
+pub struct AABox(f32, f32, f32, f32, f32, f32);
+
+fn main() {
+    println!("{:>6} {:>8} {:>8} {:>14}", "scene", "elements", "merged", "merge_touching");
+
+    for (scene, spread) in [("sparse", 6.0), ("dense", 1.0)].iter() {
+        for count in [10, 50, 200, 1000, 5000].iter() {
+            let boxes = synthetic_model(*count, *count as u64, *spread);
+
+            let start = Instant::now();
+            let merged = merging::merge_touching(&boxes);
+            let time = start.elapsed();
+
+            println!("{:>6} {:>8} {:>8} {:>14}", scene, count, merged.len(), format!("{:.3?}", time));
+        }
+    }
+}
+
+// Small boxes on the 1/16 pixel grid. With a `spread` of 6 they form many
+// separate clusters, like the details of an imported model, with 1 they
+// nearly all touch.
+fn synthetic_model(count: usize, seed: u64, spread: f32) -> Vec<AABox> {
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+
+    // Linear congruential generator, in 1/16 pixel steps below `max`.
+    let mut next = |max: f32| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+
+        ((state >> 33) % (max * 16.0) as u64) as f32 / 16.0
+    };
+
+    let side = spread * (count as f32).cbrt();
+
+    (0..count)
+        .map(|_| {
+            let (x, y, z) = (next(side), next(side), next(side));
+
+            AABox(x, y, z, x + 0.5 + next(2.5), y + 0.5 + next(2.5), z + 0.5 + next(2.5))
+        })
+        .collect()
+}
@@ -0,0 +1,308 @@
+use crate::config::Obj;
+use crate::data::{ Children, Element, Model };
+use crate::obj;
+use crate::templates;
+
+use std::collections::HashMap;
+use std::fs::{ self, File };
+use std::io::Read;
+use std::path::{ Path, PathBuf };
+
+/// Extra places to look for blockstates and models, e.g. a library mod's
+/// jar or a resource pack zip. Like the game's resource manager, the first
+/// layer that has a file wins. The project's own files come before all of
+/// them, then the built-in vanilla templates (see templates.rs) and last
+/// the client's assets, for what isn't built in.
+pub struct Assets {
+    layers: Vec<Layer>,
+}
+
+enum Layer {
+    /// A directory containing `assets/`, e.g. another project's
+    /// `src/main/resources`.
+    Dir(PathBuf),
+    /// The json and obj files under `assets/` of a zip or jar, by path.
+    Archive(HashMap<String, Vec<u8>>),
+    Templates,
+}
+
+impl Assets {
+    /// Open the sources, relative to the project directory. Archives are
+    /// read right away, directories when a file is looked up.
+    pub fn load(project_dir: &Path, sources: &[String], client_assets: Option<&String>)
+                -> Result<Assets, String> {
+        let mut layers = Vec::new();
+
+        for source in sources.iter() {
+            layers.extend(open(project_dir, source)?);
+        }
+
+        layers.push(Layer::Templates);
+
+        if let Some(source) = client_assets {
+            layers.extend(open(project_dir, source)?);
+        }
+
+        Ok(Assets { layers })
+    }
+
+    /// Read a file by its path in a resource pack, e.g.
+    /// "assets/minecraft/models/block/stairs.json".
+    pub fn read(&self, asset_path: &str) -> Option<Vec<u8>> {
+        self.layers.iter()
+            .find_map(|layer| match layer {
+                Layer::Dir(dir) => fs::read(dir.join(asset_path)).ok(),
+                Layer::Archive(files) => files.get(asset_path).cloned(),
+                Layer::Templates => templates::get(asset_path).map(|json| json.as_bytes().to_vec()),
+            })
+    }
+}
+
+fn open(project_dir: &Path, source: &str) -> Result<Option<Layer>, String> {
+    let path = project_dir.join(source);
+
+    if path.is_dir() {
+        Ok(Some(Layer::Dir(path)))
+    } else if path.is_file() {
+        Ok(Some(Layer::Archive(read_archive(&path)?)))
+    } else {
+        eprintln!("Asset source not found: {:?}", path);
+        Ok(None)
+    }
+}
+
+fn read_archive(path: &Path) -> Result<HashMap<String, Vec<u8>>, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("{:?}", e))?;
+
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| format!("{:?}: {:?}", path, e))?;
+
+    let mut files = HashMap::new();
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)
+            .map_err(|e| format!("{:?}: {:?}", path, e))?;
+
+        // Only blockstates and models are of interest.
+        let model = entry.name().ends_with(".json") || entry.name().ends_with(".obj");
+
+        if !entry.name().starts_with("assets/") || !model {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)
+            .map_err(|e| format!("{:?}: {:?}", path, e))?;
+
+        files.insert(entry.name().to_string(), bytes);
+    }
+
+    Ok(files)
+}
+
+// "mymod:block/crusher" -> ("mymod", "block/crusher"), ids without a
+// namespace are minecraft's.
+fn split_id(id: &str) -> (&str, &str) {
+    match id.find(':') {
+        Some(i) => (&id[..i], &id[i + 1..]),
+        None => ("minecraft", id),
+    }
+}
+
+/// "mymod:crusher" -> "assets/mymod/blockstates/crusher.json"
+pub fn blockstate_path(id: &str) -> String {
+    let (namespace, path) = split_id(id);
+
+    format!("assets/{}/blockstates/{}.json", namespace, path)
+}
+
+/// "mymod:block/crusher" -> "assets/mymod/models/block/crusher.json"
+pub fn model_path(id: &str) -> String {
+    let (namespace, path) = split_id(id);
+
+    format!("assets/{}/models/{}.json", namespace, path)
+}
+
+/// "mymod:models/block/press.obj" -> "assets/mymod/models/block/press.obj"
+pub fn file_path(id: &str) -> String {
+    let (namespace, path) = split_id(id);
+
+    format!("assets/{}/{}", namespace, path)
+}
+
+/// Fill in what the models inherit from their parents: the elements, if
+/// they have none, and the textures they don't set themselves. The elements
+/// of composite models are those of their children (but the hidden ones),
+/// of OBJ models the boxes of their voxelized mesh. Parents are looked up
+/// by id with `read`, meshes by path with `read_file`.
+pub fn inherit_parents<F, R>(models: &mut HashMap<String, Model>, read: F, read_file: R, settings: &Obj)
+where F: Fn(&str) -> Option<Vec<u8>>, R: Fn(&str) -> Option<Vec<u8>> {
+    let resolver = Resolver { read, read_file, settings };
+
+    for (stem, model) in models.iter_mut() {
+        resolver.resolve(model, stem, 0);
+    }
+}
+
+struct Resolver<'a, F, R> {
+    read: F,
+    read_file: R,
+    settings: &'a Obj,
+}
+
+impl<'a, F, R> Resolver<'a, F, R>
+where F: Fn(&str) -> Option<Vec<u8>>, R: Fn(&str) -> Option<Vec<u8>> {
+    fn resolve(&self, model: &mut Model, name: &str, depth: usize) {
+        inherit(model, name, &self.read);
+
+        if model.is_obj() && model.elements.is_none() {
+            model.elements = self.voxelize(model, name);
+        }
+
+        // Bounded, in case the children are nested without end.
+        if !model.is_composite() || depth > 8 {
+            return;
+        }
+
+        let children = match model.children.take() {
+            Some(Children::Named(children)) => {
+                let mut children = children.into_iter().collect::<Vec<_>>();
+
+                // By name, so the elements come in the same order every run.
+                children.sort_by(|a, b| a.0.cmp(&b.0));
+                children
+            },
+            Some(Children::Listed(children)) => children.into_iter()
+                .enumerate()
+                .map(|(i, child)| (i.to_string(), child))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut elements = Vec::new();
+
+        for (child_name, mut child) in children {
+            let hidden = model.visibility.as_ref()
+                .and_then(|visibility| visibility.get(&child_name))
+                .is_some_and(|visible| !visible);
+
+            if hidden {
+                continue;
+            }
+
+            self.resolve(&mut child, &format!("{}/{}", name, child_name), depth + 1);
+
+            // The child's texture variables are its own, the composite's
+            // fill in the ones it doesn't set.
+            let mut textures = child.textures.take().unwrap_or_default();
+
+            for (var, texture) in model.textures.iter().flatten() {
+                textures.entry(var.clone()).or_insert_with(|| texture.clone());
+            }
+
+            elements.extend(child.elements.into_iter().flatten().map(|el| Element {
+                textures: el.textures.or_else(|| Some(textures.clone())),
+                ..el
+            }));
+        }
+
+        model.elements = Some(elements);
+    }
+
+    // The boxes of the model's mesh, or nothing if it can't be read.
+    fn voxelize(&self, model: &Model, name: &str) -> Option<Vec<Element>> {
+        let id = match model.model.as_ref() {
+            Some(id) => id,
+            None => {
+                eprintln!("OBJ model without a mesh: {}", name);
+                return None;
+            },
+        };
+
+        let bytes = match (self.read_file)(&file_path(id)) {
+            Some(bytes) => bytes,
+            None => {
+                eprintln!("OBJ mesh not found: {} (of {})", id, name);
+                return None;
+            },
+        };
+
+        match obj::voxelize(&String::from_utf8_lossy(&bytes), self.settings) {
+            Ok(boxes) => Some(boxes.into_iter()
+                              .map(|b| Element::from_box(vec![b.0, b.1, b.2], vec![b.3, b.4, b.5]))
+                              .collect()),
+            Err(e) => {
+                eprintln!("{}: {}", id, e);
+                None
+            },
+        }
+    }
+}
+
+fn inherit<F>(model: &mut Model, name: &str, read: &F)
+where F: Fn(&str) -> Option<Vec<u8>> {
+    let mut parent = model.parent.clone();
+
+    // Bounded, in case the parents form a loop.
+    for _ in 0..32 {
+        let id = match parent.take() {
+            Some(id) if !id.starts_with("builtin/") => id,
+            _ => break,
+        };
+
+        let bytes = match read(&id) {
+            Some(bytes) => bytes,
+            None => {
+                // Only worth a warning if the elements were to come from it.
+                if model.elements.is_none() && model.children.is_none() {
+                    eprintln!("Parent model not found: {} (of {})", id, name);
+                }
+
+                break;
+            },
+        };
+
+        let inherited = match serde_json::from_slice::<Model>(&bytes) {
+            Ok(inherited) => inherited,
+            Err(e) => {
+                eprintln!("Parent model not readable: {} (of {}): {:?}", id, name, e);
+                break;
+            },
+        };
+
+        if model.elements.is_none() {
+            model.elements = inherited.elements;
+        }
+
+        if model.loader.is_none() {
+            model.loader = inherited.loader;
+        }
+
+        if model.model.is_none() {
+            model.model = inherited.model;
+        }
+
+        if model.children.is_none() {
+            model.children = inherited.children;
+        }
+
+        if let Some(visibility) = inherited.visibility {
+            let own = model.visibility.get_or_insert_with(HashMap::new);
+
+            for (child, visible) in visibility {
+                own.entry(child).or_insert(visible);
+            }
+        }
+
+        if let Some(textures) = inherited.textures {
+            let own = model.textures.get_or_insert_with(HashMap::new);
+
+            for (var, texture) in textures {
+                own.entry(var).or_insert(texture);
+            }
+        }
+
+        parent = inherited.parent;
+    }
+}
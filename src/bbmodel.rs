@@ -0,0 +1,345 @@
+use crate::config::Bbmodel;
+use crate::data::{ Element, Face, Faces, Model, Rotation };
+
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+/// A Blockbench project, only the parts that make up the geometry.
+#[derive(Deserialize)]
+struct Project {
+    #[serde(default)]
+    meta: Meta,
+    parent: Option<String>,
+    #[serde(default)]
+    elements: Vec<BbElement>,
+    #[serde(default)]
+    outliner: Vec<Node>,
+    /// Newer projects keep the groups' data here, the outliner only has
+    /// their uuids and children.
+    #[serde(default)]
+    groups: Vec<Group>,
+    #[serde(default)]
+    textures: Vec<Texture>,
+}
+
+#[derive(Default, Deserialize)]
+struct Meta {
+    model_format: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BbElement {
+    name: Option<String>,
+    /// Missing in older projects, where everything is a cube.
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    uuid: String,
+    #[serde(default)]
+    from: [f32; 3],
+    #[serde(default)]
+    to: [f32; 3],
+    #[serde(default)]
+    origin: [f32; 3],
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default)]
+    inflate: f32,
+    /// Whether the rotation scales the cube to fill the block, like in a
+    /// model's rotation.
+    rescale: Option<bool>,
+    export: Option<bool>,
+    visibility: Option<bool>,
+    faces: Option<BbFaces>,
+}
+
+#[derive(Deserialize)]
+struct BbFaces {
+    down: Option<BbFace>,
+    up: Option<BbFace>,
+    north: Option<BbFace>,
+    south: Option<BbFace>,
+    west: Option<BbFace>,
+    east: Option<BbFace>,
+}
+
+#[derive(Deserialize)]
+struct BbFace {
+    uv: Option<Vec<f32>>,
+    /// Index in the project's textures, null or false for a face without
+    /// one.
+    texture: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Texture {
+    /// The texture variable, e.g. "0", missing in older projects.
+    id: Option<String>,
+    /// The file name, e.g. "chain.png".
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    folder: String,
+    #[serde(default)]
+    namespace: String,
+}
+
+/// The outliner's tree: groups, and elements by uuid.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Node {
+    Element(String),
+    Group(Group),
+}
+
+#[derive(Deserialize)]
+struct Group {
+    uuid: Option<String>,
+    origin: Option<[f32; 3]>,
+    rotation: Option<[f32; 3]>,
+    export: Option<bool>,
+    visibility: Option<bool>,
+    #[serde(default)]
+    children: Vec<Node>,
+}
+
+/// Read a Blockbench project (`.bbmodel`) as a model. Elements keep their
+/// names, and their rotations and those of the groups they're in (about
+/// each group's pivot) are applied in the same order as Blockbench does.
+/// Outside of the Java block format the model's origin is the bottom
+/// center of the block, so it's moved by 8 pixels on x and z. Faces and
+/// textures are named like Blockbench exports them, e.g. "#0" for
+/// "mymod:block/chain".
+pub fn load(path: &Path, settings: &Bbmodel) -> Result<Model, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("{:?}", e))?;
+
+    let project: Project = serde_json::from_reader(file)
+        .map_err(|e| format!("{:?}: {:?}", path, e))?;
+
+    let offset = match project.meta.model_format.as_deref() {
+        None | Some("java_block") => [0.0; 3],
+        Some(_) => [8.0, 0.0, 8.0],
+    };
+
+    let by_uuid = project.elements.iter()
+        .map(|el| (el.uuid.as_str(), el))
+        .collect::<HashMap<&str, &BbElement>>();
+
+    let groups = project.groups.iter()
+        .filter_map(|group| Some((group.uuid.as_deref()?, group)))
+        .collect::<HashMap<&str, &Group>>();
+
+    let mut walk = Walk {
+        settings,
+        offset,
+        by_uuid,
+        groups,
+        textures: &project.textures,
+        placed: Vec::new(),
+        elements: Vec::new(),
+        without_origin: 0,
+    };
+
+    walk.nodes(&project.outliner, &[]);
+
+    // Elements outside of the outliner are at the top level.
+    for el in project.elements.iter() {
+        if !walk.placed.contains(&el.uuid.as_str()) {
+            walk.element(el, &[]);
+        }
+    }
+
+    if project.elements.iter().any(|el| el.kind.as_deref() == Some("mesh")) {
+        eprintln!("{:?}: mesh elements aren't supported, left out", path);
+    }
+
+    if walk.without_origin > 0 {
+        eprintln!("{:?}: {} groups without a pivot, rotated about the origin", path, walk.without_origin);
+    }
+
+    let textures = project.textures.iter()
+        .enumerate()
+        .map(|(i, texture)| (texture_var(i, texture), texture_path(texture)))
+        .collect::<HashMap<String, String>>();
+
+    Ok(Model {
+        // Blockbench leaves it empty if there is none.
+        parent: project.parent.filter(|parent| !parent.is_empty()),
+        textures: Some(textures),
+        elements: Some(walk.elements),
+        display: None,
+        loader: None,
+        children: None,
+        model: None,
+        visibility: None,
+    })
+}
+
+struct Walk<'a> {
+    settings: &'a Bbmodel,
+    offset: [f32; 3],
+    by_uuid: HashMap<&'a str, &'a BbElement>,
+    groups: HashMap<&'a str, &'a Group>,
+    textures: &'a [Texture],
+    placed: Vec<&'a str>,
+    elements: Vec<Element>,
+    // Rotated groups with no pivot in the outliner or the groups.
+    without_origin: usize,
+}
+
+impl<'a> Walk<'a> {
+    // `groups` are the rotations of the groups the nodes are in, innermost
+    // first.
+    fn nodes(&mut self, nodes: &'a [Node], groups: &[Rotation]) {
+        for node in nodes.iter() {
+            match node {
+                Node::Element(uuid) => {
+                    if let Some(el) = self.by_uuid.get(uuid.as_str()).copied() {
+                        self.placed.push(&el.uuid);
+                        self.element(el, groups);
+                    }
+                },
+                Node::Group(group) => {
+                    // What the outliner leaves out is in the groups list.
+                    let data = group.uuid.as_deref().and_then(|uuid| self.groups.get(uuid));
+
+                    let export = group.export.or_else(|| data?.export);
+                    let visibility = group.visibility.or_else(|| data?.visibility);
+
+                    // The whole group is left out, along with its elements.
+                    if !self.included(export, visibility) {
+                        self.mark_placed(&group.children);
+                        continue;
+                    }
+
+                    let origin = group.origin.or_else(|| data?.origin);
+                    let rotation = group.rotation.or_else(|| data?.rotation).unwrap_or_default();
+
+                    if origin.is_none() && rotation != [0.0; 3] {
+                        self.without_origin += 1;
+                    }
+
+                    let mut inner = self.rotations(origin.unwrap_or_default(), rotation);
+                    inner.extend(groups.iter().cloned());
+
+                    self.nodes(&group.children, &inner);
+                },
+            }
+        }
+    }
+
+    fn mark_placed(&mut self, nodes: &'a [Node]) {
+        for node in nodes.iter() {
+            match node {
+                Node::Element(uuid) => self.placed.push(uuid),
+                Node::Group(group) => self.mark_placed(&group.children),
+            }
+        }
+    }
+
+    fn element(&mut self, el: &BbElement, groups: &[Rotation]) {
+        // Locators, null objects and meshes have no box.
+        if el.kind.as_deref().unwrap_or("cube") != "cube" {
+            return;
+        }
+
+        if !self.included(el.export, el.visibility) {
+            return;
+        }
+
+        let o = self.offset;
+
+        let from = (0..3).map(|i| el.from[i] - el.inflate + o[i]).collect();
+        let to = (0..3).map(|i| el.to[i] + el.inflate + o[i]).collect();
+
+        let mut rotations = self.rotations(el.origin, el.rotation);
+
+        // Only the cube's own rotation, as exported (groups never rescale).
+        if let Some(rotation) = rotations.first_mut() {
+            rotation.rescale = el.rescale;
+        }
+
+        rotations.extend(groups.iter().cloned());
+
+        let mut rotations = rotations.into_iter();
+
+        self.elements.push(Element {
+            name: el.name.clone(),
+            rotation: rotations.next(),
+            pivots: rotations.collect(),
+            faces: el.faces.as_ref().map(|faces| self.faces(faces)),
+            ..Element::from_box(from, to)
+        });
+    }
+
+    fn faces(&self, faces: &BbFaces) -> Faces {
+        let face = |face: &Option<BbFace>| {
+            let face = face.as_ref()?;
+            let i = face.texture.as_ref()?.as_u64()? as usize;
+
+            Some(Face {
+                uv: face.uv.clone(),
+                texture: format!("#{}", texture_var(i, self.textures.get(i)?)),
+            })
+        };
+
+        Faces {
+            down: face(&faces.down),
+            up: face(&faces.up),
+            north: face(&faces.north),
+            south: face(&faces.south),
+            west: face(&faces.west),
+            east: face(&faces.east),
+        }
+    }
+
+    fn included(&self, export: Option<bool>, visibility: Option<bool>) -> bool {
+        let exported = !self.settings.honour_export || export.unwrap_or(true);
+        let visible = !self.settings.honour_visibility || visibility.unwrap_or(true);
+
+        exported && visible
+    }
+
+    // Blockbench's rotations are in degrees about the pivot, applied
+    // around x first, then y, then z.
+    fn rotations(&self, origin: [f32; 3], rotation: [f32; 3]) -> Vec<Rotation> {
+        let origin = (0..3).map(|i| origin[i] + self.offset[i]).collect::<Vec<f32>>();
+
+        ["x", "y", "z"].iter()
+            .zip(rotation.iter())
+            .filter(|(_, angle)| **angle != 0.0)
+            .map(|(axis, angle)| Rotation {
+                origin: origin.clone(),
+                axis: axis.to_string(),
+                angle: *angle,
+                rescale: None,
+            })
+            .collect()
+    }
+}
+
+// The texture's variable, its index if it has no id.
+fn texture_var(i: usize, texture: &Texture) -> String {
+    texture.id.clone().unwrap_or_else(|| i.to_string())
+}
+
+// The texture's id, as Blockbench exports it: "chain.png" in folder
+// "block" of namespace "mymod" is "mymod:block/chain".
+fn texture_path(texture: &Texture) -> String {
+    let name = texture.name.strip_suffix(".png").unwrap_or(&texture.name);
+
+    let path = if texture.folder.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", texture.folder, name)
+    };
+
+    if texture.namespace.is_empty() || texture.namespace == "minecraft" {
+        path
+    } else {
+        format!("{}:{}", texture.namespace, path)
+    }
+}
@@ -0,0 +1,146 @@
+use crate::AABox;
+
+/// What to do with geometry outside of the block's 0..16 space. Models
+/// may reach from -16 to 32, e.g. parts of multiblocks overhanging into
+/// their neighbors.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoundsPolicy {
+    /// Cut the boxes at the block's faces.
+    Clamp,
+    /// Write the boxes as they are (e.g. for outline shapes).
+    Keep,
+    /// Cut the boxes at the block's faces and write the parts in each
+    /// neighbor as a separate shape, relative to that neighbor.
+    Split,
+}
+
+impl BoundsPolicy {
+    pub fn from(s: &str) -> Option<BoundsPolicy> {
+        match s {
+            "clamp" => Some(BoundsPolicy::Clamp),
+            "keep" => Some(BoundsPolicy::Keep),
+            "split" => Some(BoundsPolicy::Split),
+            _ => None,
+        }
+    }
+}
+
+/// Position of a neighbor relative to the block, (0, 0, 0) is the block
+/// itself.
+pub type Offset = (i32, i32, i32);
+
+pub fn is_out_of_bounds(aabox: &AABox) -> bool {
+    [aabox.0, aabox.1, aabox.2].iter().any(|v| *v < 0.0) ||
+        [aabox.3, aabox.4, aabox.5].iter().any(|v| *v > 16.0)
+}
+
+/// Apply the policy to the boxes of a shape. The result is grouped by the
+/// neighbor the boxes are in, the block itself comes first.
+pub fn apply(boxes: &[AABox], policy: BoundsPolicy) -> Vec<(Offset, Vec<AABox>)> {
+    let mut groups: Vec<(Offset, Vec<AABox>)> = vec![((0, 0, 0), Vec::new())];
+
+    for aabox in boxes.iter() {
+        match policy {
+            BoundsPolicy::Keep => groups[0].1.push(aabox.clone()),
+            BoundsPolicy::Clamp => {
+                if let Some(part) = cut(aabox, (0, 0, 0)) {
+                    groups[0].1.push(part);
+                }
+            },
+            BoundsPolicy::Split => {
+                for offset in cells(aabox) {
+                    let part = match cut(aabox, offset) {
+                        Some(part) => translate(part, offset),
+                        None => continue,
+                    };
+
+                    match groups.iter_mut().find(|(o, _)| *o == offset) {
+                        Some((_, group)) => group.push(part),
+                        None => groups.push((offset, vec![part])),
+                    }
+                }
+            },
+        }
+    }
+
+    groups
+}
+
+/// Field name suffix of a neighbor, e.g. "_EAST_UP" for (1, 1, 0).
+pub fn offset_suffix(offset: Offset) -> String {
+    let names = [
+        (offset.0, "EAST", "WEST"),
+        (offset.1, "UP", "DOWN"),
+        (offset.2, "SOUTH", "NORTH"),
+    ];
+
+    let mut suffix = String::new();
+
+    for (v, positive, negative) in names.iter() {
+        let name = if *v > 0 { positive } else { negative };
+
+        for _ in 0..v.abs() {
+            suffix += "_";
+            suffix += name;
+        }
+    }
+
+    suffix
+}
+
+// The neighbors (cells of 16 pixels) a box reaches into.
+fn cells(aabox: &AABox) -> Vec<Offset> {
+    let range = |a: f32, b: f32| {
+        let first = (a / 16.0).floor() as i32;
+
+        if a < b {
+            first..=((b / 16.0).ceil() as i32 - 1)
+        } else if a % 16.0 == 0.0 && first > 0 {
+            // A flat box on a face belongs to the cell closer to the block.
+            (first - 1)..=(first - 1)
+        } else {
+            first..=first
+        }
+    };
+
+    let mut cells = Vec::new();
+
+    for x in range(aabox.0, aabox.3) {
+        for y in range(aabox.1, aabox.4) {
+            for z in range(aabox.2, aabox.5) {
+                cells.push((x, y, z));
+            }
+        }
+    }
+
+    cells
+}
+
+// The part of the box inside a cell, if it has any volume there. Flat
+// boxes are kept as long as they lie within the cell.
+fn cut(aabox: &AABox, cell: Offset) -> Option<AABox> {
+    let axis = |a: f32, b: f32, cell: i32| {
+        let (lo, hi) = (cell as f32 * 16.0, cell as f32 * 16.0 + 16.0);
+        let (a2, b2) = (a.max(lo), b.min(hi));
+
+        if a2 > b2 || (a2 == b2 && a < b) {
+            None
+        } else {
+            Some((a2, b2))
+        }
+    };
+
+    let (x0, x1) = axis(aabox.0, aabox.3, cell.0)?;
+    let (y0, y1) = axis(aabox.1, aabox.4, cell.1)?;
+    let (z0, z1) = axis(aabox.2, aabox.5, cell.2)?;
+
+    Some(AABox(x0, y0, z0, x1, y1, z1))
+}
+
+/// Move a box from block coordinates into the neighbor's coordinates.
+pub fn translate(aabox: AABox, offset: Offset) -> AABox {
+    let (dx, dy, dz) = (offset.0 as f32 * 16.0, offset.1 as f32 * 16.0, offset.2 as f32 * 16.0);
+
+    AABox(aabox.0 - dx, aabox.1 - dy, aabox.2 - dz, aabox.3 - dx, aabox.4 - dy, aabox.5 - dz)
+}
@@ -0,0 +1,179 @@
+use crate::assets;
+use crate::data::Blockstate;
+use crate::mcid_to_stem;
+
+use serde_json::Value;
+
+use std::collections::{ BTreeMap, HashMap };
+use std::fs::{ self, File };
+use std::path::Path;
+
+pub static CACHE_FILE: &str = ".voxelshape-conv-cache.json";
+
+/// What each generated file was generated from, keyed by the generated
+/// file's path. Files whose inputs hash the same as last time, and which
+/// weren't touched since, are not generated again.
+pub type Cache = BTreeMap<String, Entry>;
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    /// Hash of the source, blockstates, models (with their parents) and
+    /// settings.
+    pub inputs: String,
+    /// Hash of the generated file.
+    pub output: String,
+}
+
+/// 64-bit FNV-1a, which unlike std's hashers is the same on every run
+/// and every version.
+pub struct Hasher(u64);
+
+impl Hasher {
+    pub fn new() -> Hasher {
+        Hasher(0xcbf29ce484222325)
+    }
+
+    /// Hash a field. The length goes first, so fields can't run into each
+    /// other.
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in (bytes.len() as u64).to_le_bytes().iter().chain(bytes.iter()) {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    /// Hash a file's contents, or that there is no such file.
+    pub fn write_file(&mut self, bytes: Option<Vec<u8>>) -> Option<Vec<u8>> {
+        self.write(bytes.as_deref().unwrap_or(b"missing"));
+        bytes
+    }
+
+    pub fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// Hash everything a block's generated file depends on. `settings` is the
+/// hash of the run's settings, blockstates and the variants' models are
+/// read by id, other files (parent models, OBJ meshes) by path.
+pub fn block_inputs<B, M, R>(settings: &str, source: &Path, ids: &[String],
+                             blockstates: &HashMap<String, Blockstate>,
+                             read_blockstate: B, read_model: M, read_file: R) -> String
+where B: Fn(&str) -> Option<Vec<u8>>, M: Fn(&str) -> Option<Vec<u8>>, R: Fn(&str) -> Option<Vec<u8>> {
+    let mut hasher = Hasher::new();
+
+    hasher.write(settings.as_bytes());
+    hasher.write_file(fs::read(source).ok());
+
+    let mut models = Vec::new();
+
+    for id in ids.iter() {
+        let stem = mcid_to_stem(id);
+
+        hasher.write(id.as_bytes());
+        hasher.write_file(read_blockstate(id));
+
+        if let Some(blockstate) = blockstates.get(stem) {
+            models.extend(blockstate.variants.values().flat_map(|variant| variant.models()));
+        }
+    }
+
+    models.sort();
+    models.dedup();
+
+    for id in models {
+        hasher.write(id.as_bytes());
+
+        if let Some(model) = read_loosely(hasher.write_file(read_model(id))) {
+            model_references(&mut hasher, &model, &read_file, 0);
+        }
+    }
+
+    hasher.finish()
+}
+
+// Hash the files a model refers to: its OBJ mesh, its parents and those
+// of its inline composite children, all the way down.
+fn model_references<R>(hasher: &mut Hasher, model: &Value, read_file: &R, depth: usize)
+where R: Fn(&str) -> Option<Vec<u8>> {
+    // Bounded, in case the parents form a loop.
+    if depth > 32 {
+        return;
+    }
+
+    let field = |name: &str| model.get(name)
+        .and_then(Value::as_str)
+        .filter(|value| !value.is_empty());
+
+    // An OBJ model's mesh.
+    if let Some(mesh) = field("model") {
+        hasher.write(mesh.as_bytes());
+        hasher.write_file(read_file(&assets::file_path(mesh)));
+    }
+
+    // By name or (in some versions) as a list, see data.rs.
+    let children = match model.get("children").or_else(|| model.get("parts")) {
+        Some(Value::Object(named)) => named.values().collect(),
+        Some(Value::Array(listed)) => listed.iter().collect(),
+        _ => Vec::new(),
+    };
+
+    for child in children {
+        model_references(hasher, child, read_file, depth + 1);
+    }
+
+    if let Some(parent) = field("parent") {
+        hasher.write(parent.as_bytes());
+
+        if let Some(parent) = read_loosely(hasher.write_file(read_file(&assets::model_path(parent)))) {
+            model_references(hasher, &parent, read_file, depth + 1);
+        }
+    }
+}
+
+// Read loosely, Blockbench projects aren't models.
+fn read_loosely(bytes: Option<Vec<u8>>) -> Option<Value> {
+    serde_json::from_slice(&bytes?).ok()
+}
+
+/// Hash of a generated file, or nothing if it doesn't exist.
+pub fn output_hash(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+
+    let mut hasher = Hasher::new();
+    hasher.write(&bytes);
+
+    Some(hasher.finish())
+}
+
+pub fn is_up_to_date(cache: &Cache, project_dir: &Path, target: &Path, inputs: &str) -> bool {
+    match cache.get(&key(project_dir, target)) {
+        Some(entry) => entry.inputs == inputs &&
+            output_hash(target).as_deref() == Some(entry.output.as_str()),
+        None => false,
+    }
+}
+
+/// A generated file's path relative to the project directory.
+pub fn key(project_dir: &Path, target: &Path) -> String {
+    target.strip_prefix(project_dir)
+        .unwrap_or(target)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Load the cache, or nothing if there is none. A broken cache only means
+/// everything is generated again.
+pub fn load(project_dir: &Path) -> Cache {
+    File::open(project_dir.join(CACHE_FILE)).ok()
+        .and_then(|file| serde_json::from_reader(file).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(project_dir: &Path, cache: &Cache) -> Result<(), String> {
+    let file = File::create(project_dir.join(CACHE_FILE))
+        .map_err(|e| format!("{:?}", e))?;
+
+    serde_json::to_writer_pretty(file, cache)
+        .map_err(|e| format!("{}: {:?}", CACHE_FILE, e))
+}
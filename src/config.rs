@@ -0,0 +1,201 @@
+use crate::bounds::BoundsPolicy;
+use crate::flat::FlatPolicy;
+use crate::merging::MergeMode;
+use crate::rules::ElementRules;
+use crate::scan::{ BlockOptions, ShapeSource };
+
+use std::fs::File;
+use std::path::Path;
+
+pub static CONFIG_FILE: &str = "voxelshape-conv.json";
+
+/// Project defaults, read from `voxelshape-conv.json` in the project
+/// directory. Every field is optional, e.g.
+/// `{ "merge": "exact", "skip_variants": ["waterlogged=true"] }`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub merge: Option<MergeMode>,
+    pub skip_variants: Vec<String>,
+    pub shape: Option<ShapeSource>,
+    /// Path of the overrides file, relative to the project directory
+    /// (default = voxelshape-overrides.json).
+    pub overrides: Option<String>,
+    /// Zips, jars and directories to look for blockstates and models the
+    /// project doesn't have, relative to the project directory. The first
+    /// one that has a file wins, see assets.rs.
+    pub assets: Vec<String>,
+    /// The game's assets, e.g. the extracted client jar (or the jar
+    /// itself), for vanilla models that aren't built in. Looked at after
+    /// `assets` and the built-in templates.
+    pub client_assets: Option<String>,
+    /// Which model elements take part in the shapes, see rules.rs.
+    pub elements: ElementRules,
+    pub shapes: ShapeKinds,
+    pub snap: Snap,
+    /// Geometry outside of the block (default = keep).
+    pub bounds: Option<BoundsPolicy>,
+    /// Elements without thickness (default = keep).
+    pub flat: Option<FlatPolicy>,
+    /// Thickness of thickened flat elements in pixels (default = 1).
+    pub min_thickness: Option<f32>,
+    /// Box budget per shape (default = no limit).
+    pub max_boxes: Option<usize>,
+    pub metrics: Metrics,
+    pub obj: Obj,
+    pub bbmodel: Bbmodel,
+}
+
+/// Accuracy measurement of the generated shapes, e.g.
+/// `{ "resolution": 0.25, "warn_iou": 0.9 }` or `{ "resolution": null }`
+/// to turn it off.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Metrics {
+    /// Size of the sampled cubes in pixels (default = 0.5).
+    pub resolution: Option<f32>,
+    /// Shapes matching their model worse than this are reported
+    /// (default = 0.8).
+    pub warn_iou: f32,
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics {
+            resolution: Some(0.5),
+            warn_iou: 0.8,
+        }
+    }
+}
+
+/// Voxelization of OBJ models (forge:obj), e.g.
+/// `{ "resolution": 0.5, "offset": [8, 0, 8] }`.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Obj {
+    /// Size of the voxels in pixels, rounded so a whole number of them fit
+    /// across the block (default = 1).
+    pub resolution: f32,
+    /// Moves the mesh in pixels, e.g. `[8, 0, 8]` for meshes centered on
+    /// the origin (default = none).
+    pub offset: [f32; 3],
+}
+
+impl Default for Obj {
+    fn default() -> Obj {
+        Obj {
+            resolution: 1.0,
+            offset: [0.0; 3],
+        }
+    }
+}
+
+/// Reading of Blockbench projects (`.bbmodel`), which are used instead of
+/// the json models of the same name, e.g. `{ "honour_export": true }`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Bbmodel {
+    /// Leave out elements and groups whose "Export" is turned off
+    /// (default = false).
+    pub honour_export: bool,
+    /// Leave out hidden elements and groups (default = false).
+    pub honour_visibility: bool,
+}
+
+/// Snapping of the generated coordinates, e.g. `{ "grid": 0.5, "epsilon": 0.01 }`
+/// or `{ "grid": null }` to turn it off.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Snap {
+    /// Grid size in pixels (default = 1/16 pixel).
+    pub grid: Option<f32>,
+    /// Only coordinates closer to the grid than this are snapped.
+    pub epsilon: f32,
+}
+
+impl Default for Snap {
+    fn default() -> Snap {
+        Snap {
+            grid: Some(1.0 / 16.0),
+            epsilon: 0.001,
+        }
+    }
+}
+
+/// Minecraft asks blocks for separate outline, collision and raycast
+/// shapes. Each configured kind is generated as its own field, e.g.
+/// `FOO_OUTLINE` and `FOO_COLLISION`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ShapeKinds {
+    pub outline: Option<ShapeRules>,
+    pub collision: Option<ShapeRules>,
+    pub raycast: Option<ShapeRules>,
+}
+
+/// How a shape kind is generated, on top of the project's settings.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ShapeRules {
+    /// E.g. "hull" for an outline around all of the kind's elements.
+    pub merge: Option<MergeMode>,
+    pub bounds: Option<BoundsPolicy>,
+    pub flat: Option<FlatPolicy>,
+    pub max_boxes: Option<usize>,
+    pub elements: ElementRules,
+}
+
+impl ShapeKinds {
+    /// The configured kinds and their field name suffixes. Without any
+    /// configured kinds a single shape without a suffix is generated.
+    pub fn kinds(&self) -> Vec<(Option<&'static str>, ShapeRules)> {
+        let kinds = [
+            ("OUTLINE", &self.outline),
+            ("COLLISION", &self.collision),
+            ("RAYCAST", &self.raycast),
+        ];
+
+        let configured = kinds.iter()
+            .filter_map(|(suffix, rules)| rules.as_ref().map(|rules| (Some(*suffix), rules.clone())))
+            .collect::<Vec<_>>();
+
+        if configured.is_empty() {
+            vec![(None, ShapeRules::default())]
+        } else {
+            configured
+        }
+    }
+}
+
+impl Config {
+    /// Fill in the options a block doesn't set itself.
+    pub fn resolve(&self, options: &BlockOptions) -> BlockOptions {
+        BlockOptions {
+            output: options.output.clone(),
+            merge: options.merge.or(self.merge),
+            skip_variants: self.skip_variants.iter()
+                .chain(options.skip_variants.iter())
+                .cloned()
+                .collect(),
+            shape: options.shape.or(self.shape),
+            bounds: options.bounds.or(self.bounds),
+            flat: options.flat.or(self.flat),
+            max_boxes: options.max_boxes.or(self.max_boxes),
+        }
+    }
+}
+
+/// Load the project's config file, or the defaults if there is none.
+pub fn load(project_dir: &Path) -> Result<Config, String> {
+    let path = project_dir.join(CONFIG_FILE);
+
+    if !path.is_file() {
+        return Ok(Config::default());
+    }
+
+    let file = File::open(&path)
+        .map_err(|e| format!("{:?}", e))?;
+
+    serde_json::from_reader(file)
+        .map_err(|e| format!("{}: {:?}", CONFIG_FILE, e))
+}
@@ -1,37 +1,79 @@
+use crate::forge;
+
+use serde::de::{ self, Deserialize, Deserializer };
+use serde_json::Value;
+
 use std::collections::HashMap;
 
 pub fn cube() -> Model {
     Model {
+        parent: None,
         textures: None,
         elements: Some(vec![Element {
+            name: None,
             from: vec![ 0.0, 0.0, 0.0 ],
             to: vec![ 16.0, 16.0, 16.0 ],
             rotation: None,
             faces: None,
+            state_rotation: None,
+            pivots: Vec::new(),
+            textures: None,
         }]),
         display: None,
+        loader: None,
+        children: None,
+        model: None,
+        visibility: None,
     }
 }
 
 pub fn almost_full_cube() -> Model {
     Model {
+        parent: None,
         textures: None,
         elements: Some(vec![Element {
+            name: None,
             from: vec![ 1.0, 0.0, 1.0 ],
             to: vec![ 15.0, 16.0, 15.0 ],
             rotation: None,
             faces: None,
+            state_rotation: None,
+            pivots: Vec::new(),
+            textures: None,
         }]),
         display: None,
+        loader: None,
+        children: None,
+        model: None,
+        visibility: None,
     }
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize)]
 pub struct Blockstate {
     pub variants: HashMap<String, Variant>,
 }
 
+#[derive(Deserialize)]
+struct VanillaBlockstate {
+    variants: HashMap<String, Variant>,
+}
+
+// Vanilla and Forge (`"forge_marker": 1`) blockstates, see forge.rs.
+impl<'de> Deserialize<'de> for Blockstate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Blockstate, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+
+        if value.get("forge_marker").and_then(Value::as_i64) == Some(1) {
+            return forge::expand(&value).map_err(de::Error::custom);
+        }
+
+        let vanilla = VanillaBlockstate::deserialize(value)
+            .map_err(de::Error::custom)?;
+
+        Ok(Blockstate { variants: vanilla.variants })
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Deserialize)]
 pub struct Variant {
@@ -40,27 +82,86 @@ pub struct Variant {
     pub x: Option<f32>,
     pub y: Option<f32>,
     pub z: Option<f32>,
+    /// More models unioned into the shape, each with its own rotation
+    /// (Forge submodels).
+    #[serde(skip)]
+    pub parts: Vec<Variant>,
 }
 
 #[allow(dead_code)]
 #[derive(Deserialize)]
 pub struct Model {
+    pub parent: Option<String>,
     pub textures: Option<HashMap<String, String>>,
     pub elements: Option<Vec<Element>>,
     pub display: Option<Display>,
+    /// A custom model loader (Forge/NeoForge), e.g. "forge:composite".
+    pub loader: Option<String>,
+    /// The models a composite model is made of.
+    #[serde(alias = "parts")]
+    pub children: Option<Children>,
+    /// The mesh of an OBJ model, e.g. "mymod:models/block/press.obj".
+    pub model: Option<String>,
+    /// Composite children (by name) shown or hidden, e.g.
+    /// `{ "glass": false }`. Shown unless listed as false.
+    pub visibility: Option<HashMap<String, bool>>,
 }
 
-#[allow(dead_code)]
+/// Composite children, by name or (in some versions) as a list.
 #[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Children {
+    Named(HashMap<String, Model>),
+    Listed(Vec<Model>),
+}
+
+impl Model {
+    /// Whether the model's geometry is its children's.
+    pub fn is_composite(&self) -> bool {
+        matches!(self.loader.as_deref(), Some("forge:composite") | Some("neoforge:composite"))
+    }
+
+    /// Whether the model's geometry is an OBJ mesh, see obj.rs.
+    pub fn is_obj(&self) -> bool {
+        matches!(self.loader.as_deref(), Some("forge:obj") | Some("neoforge:obj"))
+    }
+
+    /// A loader whose geometry can't be read, so the model's elements (if
+    /// any) aren't what the game shows.
+    pub fn unsupported_loader(&self) -> Option<&str> {
+        if self.is_composite() || self.is_obj() {
+            None
+        } else {
+            self.loader.as_deref()
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Deserialize)]
 pub struct Element {
+    pub name: Option<String>,
     pub from: Vec<f32>,
     pub to: Vec<f32>,
     pub rotation: Option<Rotation>,
     pub faces: Option<Faces>,
+    /// The blockstate rotation (x, y) of a Forge submodel's element, used
+    /// instead of the variant's.
+    #[serde(skip)]
+    pub state_rotation: Option<(f32, f32)>,
+    /// Rotations after the element's own, in order, e.g. those of the
+    /// Blockbench groups it is in. Never rescaled.
+    #[serde(skip)]
+    pub pivots: Vec<Rotation>,
+    /// The textures of the model the element comes from, if that isn't
+    /// the variant's model (a Forge submodel or a composite child). Its
+    /// faces' texture variables are resolved against these.
+    #[serde(skip)]
+    pub textures: Option<HashMap<String, String>>,
 }
 
 #[allow(dead_code)]
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Faces {
     pub down: Option<Face>,
     pub up: Option<Face>,
@@ -71,18 +172,20 @@ pub struct Faces {
 }
 
 #[allow(dead_code)]
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Face {
-    pub uv: Vec<f32>,
+    /// Left out to take it from the element's position.
+    pub uv: Option<Vec<f32>>,
     pub texture: String,
 }
 
 #[allow(dead_code)]
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Rotation {
     pub origin: Vec<f32>,
     pub axis: String,
     pub angle: f32,
+    pub rescale: Option<bool>,
 }
 
 #[allow(dead_code)]
@@ -105,3 +208,39 @@ pub struct Transform {
     pub translation: Option<Vec<f32>>,
     pub scale: Option<Vec<f32>>,
 }
+
+impl Variant {
+    /// An unrotated variant of a model.
+    pub fn from_model(model: String) -> Variant {
+        Variant {
+            model,
+            uvlock: None,
+            x: None,
+            y: None,
+            z: None,
+            parts: Vec::new(),
+        }
+    }
+
+    /// The ids of the variant's model and those of its parts.
+    pub fn models(&self) -> impl Iterator<Item = &str> {
+        Some(self.model.as_str()).into_iter()
+            .chain(self.parts.iter().map(|part| part.model.as_str()))
+    }
+}
+
+impl Element {
+    /// An unrotated element without faces.
+    pub fn from_box(from: Vec<f32>, to: Vec<f32>) -> Element {
+        Element {
+            name: None,
+            from,
+            to,
+            rotation: None,
+            faces: None,
+            state_rotation: None,
+            pivots: Vec::new(),
+            textures: None,
+        }
+    }
+}
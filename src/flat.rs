@@ -0,0 +1,79 @@
+use crate::data::Element;
+
+/// What to do with elements that have no thickness on an axis, e.g. the
+/// planes of cross models, panes and decals. As boxes they have no volume,
+/// which the game can't select or collide with.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlatPolicy {
+    /// Leave them out of the shape.
+    Drop,
+    /// Write them as they are.
+    Keep,
+    /// Grow them to a minimum thickness, centered on the plane.
+    Thicken,
+}
+
+impl FlatPolicy {
+    pub fn from(s: &str) -> Option<FlatPolicy> {
+        match s {
+            "drop" => Some(FlatPolicy::Drop),
+            "keep" => Some(FlatPolicy::Keep),
+            "thicken" => Some(FlatPolicy::Thicken),
+            _ => None,
+        }
+    }
+}
+
+// The axes ("x", "y", "z") on which the element has no thickness.
+fn flat_axes(el: &Element) -> Vec<&'static str> {
+    ["x", "y", "z"].iter()
+        .enumerate()
+        .filter(|(i, _)| (el.to[*i] - el.from[*i]).abs() < 0.0001)
+        .map(|(_, axis)| *axis)
+        .collect()
+}
+
+/// Apply the policy to the elements of a shape. Every flat element gets a
+/// line in `notes`, so it can be reported.
+pub fn apply(elements: &[&Element], policy: FlatPolicy, thickness: f32,
+             notes: &mut Vec<String>) -> Vec<Element> {
+    let mut result = Vec::new();
+
+    for el in elements.iter() {
+        let axes = flat_axes(el);
+
+        if axes.is_empty() || policy == FlatPolicy::Keep {
+            result.push((*el).clone());
+            continue;
+        }
+
+        let what = match el.name.as_ref() {
+            Some(name) => format!("element \"{}\"", name),
+            None => format!("element {:?}..{:?}", el.from, el.to),
+        };
+
+        match policy {
+            FlatPolicy::Drop => notes.push(format!("{} flat on {}, dropped", what, axes.join(", "))),
+            _ => {
+                let mut el = (*el).clone();
+
+                for i in 0..3 {
+                    if (el.to[i] - el.from[i]).abs() < 0.0001 {
+                        let center = el.from[i];
+                        el.from[i] = center - thickness / 2.0;
+                        el.to[i] = center + thickness / 2.0;
+                    }
+                }
+
+                notes.push(format!(
+                    "{} flat on {}, thickened to {}",
+                    what, axes.join(", "), thickness));
+
+                result.push(el);
+            },
+        }
+    }
+
+    result
+}
@@ -0,0 +1,304 @@
+use crate::data::{ Blockstate, Variant };
+
+use serde_json::{ Map, Value };
+
+use std::collections::HashMap;
+
+// A property's variants by value.
+type Values<'a> = Vec<(&'a str, &'a Map<String, Value>)>;
+
+/// Expand a Forge (v1) blockstate into a vanilla one with a variant per
+/// state, e.g.
+///
+/// ```json
+/// { "forge_marker": 1,
+///   "defaults": { "model": "mymod:block/lamp" },
+///   "variants": {
+///     "facing": { "north": {}, "east": { "y": 90 } },
+///     "lit": { "true": { "submodel": { "glow": { "model": "mymod:block/glow" } } }, "false": {} },
+///     "inventory": [{}]
+/// } }
+/// ```
+///
+/// Each state starts out as `defaults`, then gets each of its properties'
+/// values and last the variant with the state's full key (e.g.
+/// "facing=east,lit=true"), if there is one. Submodels become parts of the
+/// variant, which are unioned into its shape with their own rotation.
+pub fn expand(value: &Value) -> Result<Blockstate, String> {
+    let empty = Map::new();
+
+    let defaults = value.get("defaults")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+
+    let listed = value.get("variants")
+        .and_then(Value::as_object)
+        .ok_or("Forge blockstate without variants".to_string())?;
+
+    let mut properties: Vec<(&str, Values)> = Vec::new();
+    let mut full: HashMap<String, &Map<String, Value>> = HashMap::new();
+
+    for (key, variant) in listed.iter() {
+        // Only used for the item.
+        if key == "inventory" {
+            continue;
+        }
+
+        match as_property(key, variant) {
+            Some(values) => properties.push((key, values)),
+            None => {
+                // Random variants aren't supported, the first one is used.
+                let variant = match variant {
+                    Value::Array(list) => list.first(),
+                    _ => Some(variant),
+                };
+
+                if let Some(variant) = variant.and_then(Value::as_object) {
+                    let key = if key == "normal" { String::new() } else { full_key(key) };
+                    full.insert(key, variant);
+                }
+            },
+        }
+    }
+
+    // Vanilla keys list the properties in alphabetical order.
+    properties.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut states: Vec<(String, Map<String, Value>)> = vec![(String::new(), defaults.clone())];
+
+    for (name, values) in properties.iter() {
+        states = states.into_iter()
+            .flat_map(|(key, state)| values.iter().map(move |(value, variant)| {
+                let key = if key.is_empty() {
+                    format!("{}={}", name, value)
+                } else {
+                    format!("{},{}={}", key, name, value)
+                };
+
+                let mut state = state.clone();
+                merge(&mut state, variant);
+
+                (key, state)
+            }))
+            .collect();
+    }
+
+    // Without properties the fully specified variants are the states.
+    if properties.is_empty() && !full.is_empty() {
+        states = full.keys()
+            .map(|key| (key.clone(), defaults.clone()))
+            .collect();
+    }
+
+    let mut variants = HashMap::new();
+
+    for (key, mut state) in states {
+        if let Some(variant) = full.get(&key) {
+            merge(&mut state, variant);
+        }
+
+        match to_variant(&state) {
+            Some(variant) => {
+                variants.insert(key, variant);
+            },
+            None => eprintln!("No model for Forge blockstate variant: {}", key),
+        }
+    }
+
+    Ok(Blockstate { variants })
+}
+
+// The key with its properties in alphabetical order, like the states'
+// keys, e.g. "lit=true,facing=east" is "facing=east,lit=true".
+fn full_key(key: &str) -> String {
+    let mut properties = key.split(',')
+        .map(str::trim)
+        .filter(|property| !property.is_empty())
+        .collect::<Vec<&str>>();
+
+    properties.sort_by_key(|property| property.split('=').next());
+    properties.join(",")
+}
+
+// A property's variants by value, e.g. "facing": { "north": {..}, .. }.
+// Anything else is a single, fully specified variant.
+fn as_property<'a>(key: &str, variant: &'a Value) -> Option<Values<'a>> {
+    if key.contains('=') || key == "normal" {
+        return None;
+    }
+
+    let values = variant.as_object()?;
+
+    if values.is_empty() {
+        return None;
+    }
+
+    values.iter()
+        .map(|(value, variant)| Some((value.as_str(), variant.as_object()?)))
+        .collect()
+}
+
+// Later values win, textures and submodels are merged by name.
+fn merge(state: &mut Map<String, Value>, variant: &Map<String, Value>) {
+    for (key, value) in variant.iter() {
+        let nested = key == "textures" || key == "submodel";
+
+        match (state.get_mut(key), value) {
+            (Some(Value::Object(own)), Value::Object(other)) if nested => {
+                for (name, value) in other.iter() {
+                    own.insert(name.clone(), value.clone());
+                }
+            },
+            _ => {
+                state.insert(key.clone(), value.clone());
+            },
+        }
+    }
+}
+
+fn to_variant(state: &Map<String, Value>) -> Option<Variant> {
+    let model = model_id(state.get("model")?.as_str()?);
+    let number = |key: &str| state.get(key).and_then(Value::as_f64).map(|v| v as f32);
+
+    let mut parts = Vec::new();
+
+    match state.get("submodel") {
+        Some(Value::String(model)) => parts.push(Variant::from_model(model_id(model))),
+        Some(Value::Object(submodels)) => {
+            // By name, so the parts come in the same order every run.
+            let mut names = submodels.keys().collect::<Vec<&String>>();
+            names.sort();
+
+            for name in names {
+                match submodels[name].as_object().and_then(to_variant) {
+                    // Submodels of submodels are parts all the same.
+                    Some(mut part) => {
+                        let nested = std::mem::take(&mut part.parts);
+
+                        parts.push(part);
+                        parts.extend(nested);
+                    },
+                    None => eprintln!("No model for Forge submodel: {}", name),
+                }
+            }
+        },
+        _ => {},
+    }
+
+    Some(Variant {
+        model,
+        uvlock: state.get("uvlock").and_then(Value::as_bool),
+        x: number("x"),
+        y: number("y"),
+        z: number("z"),
+        parts,
+    })
+}
+
+// Forge's model ids are in models/block/ unless they have a path of their
+// own, e.g. "mymod:lamp" is "mymod:block/lamp".
+fn model_id(model: &str) -> String {
+    let path = model.find(':').map_or(model, |i| &model[i + 1..]);
+
+    if path.contains('/') {
+        return model.to_string();
+    }
+
+    match model.find(':') {
+        Some(i) => format!("{}:block/{}", &model[..i], path),
+        None => format!("block/{}", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand;
+    use crate::data::Blockstate;
+
+    fn blockstate(json: &str) -> Blockstate {
+        expand(&serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    fn keys(blockstate: &Blockstate) -> Vec<&str> {
+        let mut keys = blockstate.variants.keys()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn defaults() {
+        let bs = blockstate(r#"{ "forge_marker": 1,
+            "defaults": { "model": "mymod:lamp", "y": 180 },
+            "variants": { "normal": [{}], "inventory": [{}] } }"#);
+
+        assert_eq!(keys(&bs), vec![""]);
+        assert_eq!(bs.variants[""].model, "mymod:block/lamp");
+        assert_eq!(bs.variants[""].y, Some(180.0));
+    }
+
+    #[test]
+    fn cartesian_product() {
+        let bs = blockstate(r#"{ "forge_marker": 1,
+            "defaults": { "model": "mymod:block/lamp" },
+            "variants": {
+                "lit": { "true": { "x": 90 }, "false": {} },
+                "facing": { "north": {}, "east": { "y": 90 } } } }"#);
+
+        assert_eq!(keys(&bs), vec![
+            "facing=east,lit=false", "facing=east,lit=true",
+            "facing=north,lit=false", "facing=north,lit=true"]);
+
+        let variant = &bs.variants["facing=east,lit=true"];
+        assert_eq!((variant.x, variant.y), (Some(90.0), Some(90.0)));
+    }
+
+    #[test]
+    fn full_key_merge() {
+        let bs = blockstate(r#"{ "forge_marker": 1,
+            "defaults": { "model": "mymod:block/lamp" },
+            "variants": {
+                "facing": { "north": {}, "east": { "y": 90 } },
+                "facing=east": { "model": "mymod:block/lamp_east" } } }"#);
+
+        assert_eq!(keys(&bs), vec!["facing=east", "facing=north"]);
+        assert_eq!(bs.variants["facing=east"].model, "mymod:block/lamp_east");
+        assert_eq!(bs.variants["facing=east"].y, Some(90.0));
+        assert_eq!(bs.variants["facing=north"].model, "mymod:block/lamp");
+    }
+
+    #[test]
+    fn mis_ordered_key() {
+        let bs = blockstate(r#"{ "forge_marker": 1,
+            "defaults": { "model": "mymod:block/lamp" },
+            "variants": {
+                "facing": { "north": {}, "east": {} },
+                "lit": { "true": {}, "false": {} },
+                "lit=true, facing=east": { "y": 270 } } }"#);
+
+        assert_eq!(keys(&bs).len(), 4);
+        assert_eq!(bs.variants["facing=east,lit=true"].y, Some(270.0));
+        assert_eq!(bs.variants["facing=east,lit=false"].y, None);
+    }
+
+    #[test]
+    fn submodels() {
+        let bs = blockstate(r#"{ "forge_marker": 1,
+            "defaults": { "model": "mymod:block/lamp",
+                          "submodel": { "stand": { "model": "mymod:stand" } } },
+            "variants": {
+                "lit": {
+                    "true": { "submodel": { "glow": { "model": "mymod:block/glow", "x": 90 } } },
+                    "false": {} } } }"#);
+
+        let parts = |key: &str| bs.variants[key].parts.iter()
+            .map(|part| (part.model.as_str(), part.x))
+            .collect::<Vec<(&str, Option<f32>)>>();
+
+        assert_eq!(parts("lit=false"), vec![("mymod:block/stand", None)]);
+        assert_eq!(parts("lit=true"), vec![
+            ("mymod:block/glow", Some(90.0)), ("mymod:block/stand", None)]);
+    }
+}
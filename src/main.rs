@@ -3,31 +3,48 @@ extern crate serde;
 extern crate serde_derive;
 extern crate serde_json;
 
+mod assets;
+mod bbmodel;
+mod bounds;
+mod cache;
+mod config;
 mod data;
+mod flat;
+mod forge;
 mod scan;
+mod templates;
 mod merging;
-
+mod obj;
+mod metrics;
+mod overrides;
+mod parallel;
+mod rules;
+mod watch;
+
+use bounds::BoundsPolicy;
+use flat::FlatPolicy;
 use data::{ Blockstate, Model, Element };
-use scan::BlockInfo;
+use merging::MergeMode;
+use scan::{ BlockInfo, Language, ShapeSource };
 
 use std::env;
 use std::ops::{ Add, Sub };
 use std::fs::{ self, File, OpenOptions };
 use std::io::{ self, BufWriter, Write };
 use std::str::FromStr;
-use std::path::{ Path, PathBuf };
+use std::path::PathBuf;
 use std::collections::{ HashMap, HashSet };
 
 use serde::Deserialize;
 
-static VERSION: &'static str = "0.1.1";
+static VERSION: &str = "0.1.1";
 
 #[derive(Clone)]
 /// AABox - Axis-Aligned Box, all faces face either +-X, +-Y or +-Z.
 pub struct AABox(f32, f32, f32, f32, f32, f32);
 
 impl AABox {
-    fn from(from: &Vec<f32>, to: &Vec<f32>) -> AABox {
+    fn from(from: &[f32], to: &[f32]) -> AABox {
         if from.len() != 3 { panic!("Not a 3D-vector"); }
         if to.len() != 3 { panic!("Not a 3D-vector"); }
 
@@ -54,7 +71,7 @@ impl Axis {
 struct Vec3(f32, f32, f32);
 
 impl Vec3 {
-    fn from(vec: &Vec<f32>) -> Vec3 {
+    fn from(vec: &[f32]) -> Vec3 {
         if vec.len() != 3 { panic!("Not a 3D-vector"); }
 
         Vec3(vec[0], vec[1], vec[2])
@@ -77,45 +94,69 @@ impl Sub for Vec3 {
     }
 }
 
+/// Blockstate rotation of a model. The game has no `z` rotation.
 struct ModelRotation {
     x: f32,
     y: f32,
-    z: f32,
 }
 
 /// Approximates a non-axis-aligned box.
 fn approximate(el: &Element, modrot: &ModelRotation) -> AABox {
+    let verts = transform(el, modrot);
+
+    let min_x = verts.iter().min_by(|a, b| a.0.partial_cmp(&b.0).unwrap()).expect("0-size vector???").0;
+    let max_x = verts.iter().max_by(|a, b| a.0.partial_cmp(&b.0).unwrap()).expect("0-size vector???").0;
+
+    let min_y = verts.iter().min_by(|a, b| a.1.partial_cmp(&b.1).unwrap()).expect("0-size vector???").1;
+    let max_y = verts.iter().max_by(|a, b| a.1.partial_cmp(&b.1).unwrap()).expect("0-size vector???").1;
+
+    let min_z = verts.iter().min_by(|a, b| a.2.partial_cmp(&b.2).unwrap()).expect("0-size vector???").2;
+    let max_z = verts.iter().max_by(|a, b| a.2.partial_cmp(&b.2).unwrap()).expect("0-size vector???").2;
+
+    AABox(min_x, min_y, min_z, max_x, max_y, max_z)
+}
+
+/// The element's corners (in `into_verts` order) after the element's own
+/// rotation, its pivots and the blockstate rotation.
+fn transform(el: &Element, modrot: &ModelRotation) -> Vec<Vec3> {
+    let modrot = match el.state_rotation {
+        Some((x, y)) => ModelRotation { x, y },
+        None => ModelRotation { x: modrot.x, y: modrot.y },
+    };
+
     let verts = into_verts(AABox::from(&el.from, &el.to));
     let mut verts = match &el.rotation {
-        Some(rot) => rotate(verts, Vec3::from(&rot.origin), Axis::from(&rot.axis), rot.angle),
+        Some(rot) => {
+            let origin = Vec3::from(&rot.origin);
+            let verts = rotate(verts, origin, Axis::from(&rot.axis), rot.angle);
+
+            if rot.rescale.unwrap_or(false) {
+                rescale(verts, origin, Axis::from(&rot.axis), rot.angle)
+            } else {
+                verts
+            }
+        },
         None => verts,
     };
 
+    for rot in el.pivots.iter() {
+        verts = rotate(verts, Vec3::from(&rot.origin), Axis::from(&rot.axis), rot.angle);
+    }
+
     let origin = Vec3(8.0, 8.0, 8.0);
 
-    // Apply model rotation based on blockstate.
+    // Apply model rotation based on blockstate. Like the game, rotate by
+    // x first and by y second, both clockwise when looking at the block
+    // from the positive end of the axis (y = 90 turns north into east).
     if modrot.x != 0.0 {
-        verts = rotate(verts, origin, Axis::X, -modrot.x); // Assuming the - is needed
+        verts = rotate(verts, origin, Axis::X, -modrot.x);
     }
 
     if modrot.y != 0.0 {
         verts = rotate(verts, origin, Axis::Y, -modrot.y);
     }
 
-    if modrot.z != 0.0 {
-        verts = rotate(verts, origin, Axis::Z, -modrot.z); // Assuming the - is needed
-    }
-
-    let min_x = verts.iter().min_by(|a, b| a.0.partial_cmp(&b.0).unwrap()).expect("0-size vector???").0;
-    let max_x = verts.iter().max_by(|a, b| a.0.partial_cmp(&b.0).unwrap()).expect("0-size vector???").0;
-
-    let min_y = verts.iter().min_by(|a, b| a.1.partial_cmp(&b.1).unwrap()).expect("0-size vector???").1;
-    let max_y = verts.iter().max_by(|a, b| a.1.partial_cmp(&b.1).unwrap()).expect("0-size vector???").1;
-
-    let min_z = verts.iter().min_by(|a, b| a.2.partial_cmp(&b.2).unwrap()).expect("0-size vector???").2;
-    let max_z = verts.iter().max_by(|a, b| a.2.partial_cmp(&b.2).unwrap()).expect("0-size vector???").2;
-
-    AABox(min_x, min_y, min_z, max_x, max_y, max_z)
+    verts
 }
 
 fn into_verts(el: AABox) -> Vec<Vec3> {
@@ -140,6 +181,9 @@ fn into_verts(el: AABox) -> Vec<Vec3> {
     ]
 }
 
+/// Rotate counterclockwise by `angle` degrees when looking at 'origin' from
+/// the positive end of the axis (right-handed), which is how element
+/// rotations in models work.
 fn rotate(mut el: Vec<Vec3>, origin: Vec3, axis: Axis, angle: f32) -> Vec<Vec3> {
     for v in el.iter_mut() {
         // Adjust v so that rotation occurs as if v was rotated around
@@ -153,17 +197,14 @@ fn rotate(mut el: Vec<Vec3>, origin: Vec3, axis: Axis, angle: f32) -> Vec<Vec3>
             Axis::Z => (v.1, v.0),
         };
 
-        let pi = std::f32::consts::PI;
-        let de = (2.0 * pi) / 360.0;
-
-        // NOTE: this line is a result of trial and error.
-        // If the format of input changes, this is likely broken.
-        let angle = -angle * de;
+        // The mapped planes (z, y), (x, z) and (y, x) are seen from the
+        // negative end of the axis, hence the negated angle.
+        let (sin, cos) = sin_cos_deg(-angle);
 
         // The actual rotation.
         let (x, y) = (
-            (x * angle.cos() - y * angle.sin()),
-            (y * angle.cos() + x * angle.sin())
+            (x * cos - y * sin),
+            (y * cos + x * sin)
         );
 
         // Un-map axis.
@@ -180,6 +221,66 @@ fn rotate(mut el: Vec<Vec3>, origin: Vec3, axis: Axis, angle: f32) -> Vec<Vec3>
     el
 }
 
+/// Scale a rotated element back to its full size across the block, on
+/// the axes other than the rotation axis. Like the game, any angle other
+/// than 22.5 degrees is scaled as if it was 45 degrees.
+fn rescale(mut el: Vec<Vec3>, origin: Vec3, axis: Axis, angle: f32) -> Vec<Vec3> {
+    let scale = if angle.abs() == 22.5 {
+        1.0 / 22.5_f32.to_radians().cos()
+    } else {
+        1.0 / 45.0_f32.to_radians().cos()
+    };
+
+    let scale = match axis {
+        Axis::X => Vec3(1.0, scale, scale),
+        Axis::Y => Vec3(scale, 1.0, scale),
+        Axis::Z => Vec3(scale, scale, 1.0),
+    };
+
+    for v in el.iter_mut() {
+        let d = *v - origin;
+        *v = Vec3(d.0 * scale.0, d.1 * scale.1, d.2 * scale.2) + origin;
+    }
+
+    el
+}
+
+// Multiples of 90 degrees (all blockstate rotations) are kept exact, f32
+// trig would turn 16 into 15.999999.
+fn sin_cos_deg(angle: f32) -> (f32, f32) {
+    if angle % 90.0 == 0.0 {
+        match (angle / 90.0) as i32 % 4 {
+            0 => (0.0, 1.0),
+            1 | -3 => (1.0, 0.0),
+            2 | -2 => (0.0, -1.0),
+            _ => (-1.0, 0.0),
+        }
+    } else {
+        angle.to_radians().sin_cos()
+    }
+}
+
+/// Round coordinates which are within `epsilon` of the grid to the grid,
+/// to get rid of float noise from rotations.
+fn snap(aabox: AABox, snap: &config::Snap) -> AABox {
+    let grid = match snap.grid {
+        Some(grid) if grid > 0.0 => grid,
+        _ => return aabox,
+    };
+
+    let one = |v: f32| {
+        let snapped = (v / grid).round() * grid;
+
+        if (v - snapped).abs() <= snap.epsilon {
+            snapped + 0.0 // No -0.0
+        } else {
+            v
+        }
+    };
+
+    AABox(one(aabox.0), one(aabox.1), one(aabox.2), one(aabox.3), one(aabox.4), one(aabox.5))
+}
+
 #[allow(dead_code)]
 struct Style {
     start_indent_level: u32,
@@ -194,42 +295,104 @@ fn mkindent(level: u32, style: &Style) -> String {
     }
 }
 
+// Numbers are always written with a fraction ("16.0", "7.5"), kotlin
+// doesn't widen integer literals to Double.
+fn format_number(v: f32) -> String {
+    let s = format!("{}", v + 0.0);
+
+    if s.contains('.') {
+        s
+    } else {
+        s + ".0"
+    }
+}
+
 fn format_cuboid_expr(aabox: &AABox, _style: &Style) -> String {
     format!(
         "Block.makeCuboidShape({}, {}, {}, {}, {}, {})",
-        aabox.0, aabox.1, aabox.2, aabox.3, aabox.4, aabox.5)
+        format_number(aabox.0), format_number(aabox.1), format_number(aabox.2),
+        format_number(aabox.3), format_number(aabox.4), format_number(aabox.5))
 }
 
 fn usage() -> ! {
-    println!("voxelshape-conv [project directory]");
+    println!("voxelshape-conv [options] [project directory]");
     println!("    'project directory' is optional and can be used to change");
     println!("    which directory the program will operate on (default = cwd).");
-    println!("");
+    println!();
+    println!("    --infer-ids    Also find block ids from registration code, e.g.");
+    println!("                   'new ResourceLocation(\"mymod\", \"crusher\"), new CrusherBlock(..)'");
+    println!("                   or 'setRegistryName(\"crusher\")'.");
+    println!("    --kotlin       Generate kotlin objects for blocks found in kotlin sources");
+    println!("                   (default = java classes).");
+    println!("    --rebuild      Generate every class, even if its inputs didn't change since");
+    println!("                   the last run (see '{}').", cache::CACHE_FILE);
+    println!("    --watch        Keep running and generate the classes again whenever");
+    println!("                   a blockstate, model or source file changes.");
+    println!();
+    println!("    Project defaults (merge mode, skipped variants, shape source) are read");
+    println!("    from '{}' in the project directory, if it exists.", config::CONFIG_FILE);
+    println!("    Hand-tuned boxes per block are read from '{}'.", overrides::OVERRIDES_FILE);
+    println!();
     println!("    VoxelShape Converter by Garophel");
     println!("    Version {}", VERSION);
 
     std::process::exit(0);
 }
 
-fn main() {
-    // Get first command-line argument or the string ".".
-    let first = env::args().nth(1).unwrap_or(".".to_string());
+/// Options given on the command line.
+struct Settings {
+    /// The directory to scan.
+    project_dir: PathBuf,
+    infer_ids: bool,
+    emit_kotlin: bool,
+    /// Ignore the cache.
+    rebuild: bool,
+    watch: bool,
+}
+
+fn parse_args() -> Settings {
+    let mut settings = Settings {
+        project_dir: PathBuf::from("."),
+        infer_ids: false,
+        emit_kotlin: false,
+        rebuild: false,
+        watch: false,
+    };
 
-    if first == "-h" || first == "--help" {
-        // This call never returns (exit is called).
-        usage();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            // This call never returns (exit is called).
+            "-h" | "--help" => usage(),
+            "--infer-ids" => settings.infer_ids = true,
+            "--kotlin" => settings.emit_kotlin = true,
+            "--rebuild" => settings.rebuild = true,
+            "--watch" => settings.watch = true,
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown option: {}", arg);
+                usage();
+            },
+            _ => settings.project_dir = PathBuf::from_str(&arg).unwrap(),
+        }
     }
 
-    // The directory to scan.
-    let path = PathBuf::from_str(&first).unwrap();
+    settings
+}
+
+fn main() {
+    let settings = parse_args();
 
-    match automatic(path) {
-        Err(e) => eprintln!("Err: {:?}", e),
-        _ => {},
+    let result = if settings.watch {
+        watch::run(&settings)
+    } else {
+        automatic(&settings).map(|_| ())
+    };
+
+    if let Err(e) = result {
+        eprintln!("Err: {:?}", e);
     }
 }
 
-fn mcid_to_stem<'a>(id: &'a str) -> &'a str {
+fn mcid_to_stem(id: &str) -> &str {
     let file_start = id.find(":").unwrap_or(0);
     let stem_start = id[file_start..].find("/").unwrap_or(0) + file_start;
 
@@ -270,20 +433,39 @@ fn fieldify(s: &str) -> String {
     String::from_iter(result)
 }
 
-fn load_files<F, T>(files: &Vec<&PathBuf>, keep: F)
-                    -> Result<HashMap<String, T>, String>
-where F: Fn(&str) -> bool, for<'de> T: Deserialize<'de> {
-    let mut map = HashMap::new();
+/// Add a note to a field's comment, e.g. "facing=east (note)".
+fn append_note(comment: &str, note: &str) -> String {
+    match comment.strip_suffix(')') {
+        Some(start) => format!("{}, {})", start, note),
+        None => format!("{} ({})", comment, note),
+    }
+}
+
+// Check whether a variant key (e.g. "facing=north,powered=true") has all
+// the properties listed in the pattern (e.g. "powered=true").
+fn variant_matches(key: &str, pattern: &str) -> bool {
+    let props = key.split(',')
+        .map(|prop| prop.trim())
+        .collect::<Vec<&str>>();
+
+    pattern.split(',')
+        .map(|prop| prop.trim())
+        .filter(|prop| !prop.is_empty())
+        .all(|prop| props.contains(&prop))
+}
 
-    for path in files.iter() {
+fn load_files<F, T>(files: &[&PathBuf], keep: F)
+                    -> Result<HashMap<String, T>, String>
+where F: Fn(&str) -> bool + Sync, for<'de> T: Deserialize<'de> + Send {
+    let loaded = parallel::map(files, |path| -> Result<Option<(String, T)>, String> {
         let stem = path.file_stem()
-            .ok_or(format!("No file stem in path"))?
+            .ok_or("No file stem in path".to_string())?
             .to_str()
-            .ok_or(format!("File stem inconversible into UTF-8"))?
+            .ok_or("File stem inconversible into UTF-8".to_string())?
             .to_owned();
 
         if !keep(&stem) {
-            continue;
+            return Ok(None);
         }
 
         let file = File::open(path)
@@ -292,12 +474,29 @@ where F: Fn(&str) -> bool, for<'de> T: Deserialize<'de> {
         let t = serde_json::from_reader(file)
             .map_err(|e| format!("{:?}", e))?;
 
-        map.insert(stem, t);
+        Ok(Some((stem, t)))
+    });
+
+    let mut map = HashMap::new();
+
+    // In the files' order, so later files win like before.
+    for entry in loaded {
+        if let Some((stem, t)) = entry? {
+            map.insert(stem, t);
+        }
     }
 
     Ok(map)
 }
 
+/// Files by stem, the way blockstates and models are looked up. Later
+/// files win, like when loading them.
+fn by_stem<'a>(files: &[&'a PathBuf]) -> HashMap<String, &'a PathBuf> {
+    files.iter()
+        .filter_map(|path| Some((path.file_stem()?.to_str()?.to_string(), *path)))
+        .collect()
+}
+
 fn ends_with_variant_index(s: &str) -> bool {
     let mut numbers = false;
 
@@ -326,8 +525,8 @@ fn increment_variant_index(ss: &mut String) {
     for i in 0 .. s.len() - 1 {
         let j = s.len() - 1 - i;
 
-        if '0' as u8 <= s[j] && s[j] <= '9' as u8 {
-            val += (s[j] - '0' as u8) as i32 * 10_i32.pow(i as u32);
+        if b'0' <= s[j] && s[j] <= b'9' {
+            val += (s[j] - b'0') as i32 * 10_i32.pow(i as u32);
             numstart = j;
         } else {
             break;
@@ -337,24 +536,70 @@ fn increment_variant_index(ss: &mut String) {
     ss.replace_range(numstart.., &format!("{}", val + 1));
 }
 
-fn automatic<P: AsRef<Path>>(path: P) -> Result<(), String> {
+/// Generate the classes of the project's blocks. Returns the paths of all
+/// of them, whether generated or up to date.
+fn automatic(settings: &Settings) -> Result<Vec<PathBuf>, String> {
     let style = Style {
         start_indent_level: 1,
         tab_width: 4,
         expand_tab: true,
     };
 
+    let config = config::load(&settings.project_dir)?;
+
+    // Things worth knowing about the generated shapes, printed at the end.
+    let mut report = Vec::new();
+    let mut ious = Vec::new();
+
+    let overrides_path = settings.project_dir.join(
+        config.overrides.as_deref().unwrap_or(overrides::OVERRIDES_FILE));
+
+    let overrides = overrides::load(&overrides_path)?;
+
     // ALL files discovered in the scanned directory structure.
     // (minus blacklist in scan.rs).
-    let paths = scan::discover_files(&path.as_ref()).unwrap();
+    let paths = scan::discover_files(&settings.project_dir)?;
+
+    let source_files = paths.iter()
+        .filter(|path| Language::from_path(path).is_some())
+        .collect::<Vec<&PathBuf>>();
 
-    let blocks = paths.iter()
-        .filter(|path| path.extension().map_or(false, |ext| ext == "java"))
-        .map(|path| scan::process_java_file(path))
+    let java_files = source_files.iter()
+        .filter(|path| Language::from_path(path) == Some(Language::Java))
+        .cloned()
+        .collect::<Vec<&PathBuf>>();
+
+    let mut blocks = parallel::map(&source_files, |path| scan::process_source_file(path, settings.emit_kotlin))
+        .into_iter()
         .flatten()
-        .filter(|binfo| binfo.ids.len() > 0)
         .collect::<Vec<BlockInfo>>();
 
+    if settings.infer_ids {
+        let registered = scan::infer_registered_ids(&java_files);
+
+        for binfo in blocks.iter_mut() {
+            for id in registered.get(&binfo.classname).into_iter().flatten() {
+                if !binfo.ids.contains(id) {
+                    binfo.ids.push(id.clone());
+                }
+            }
+        }
+    }
+
+    blocks.retain(|binfo| !binfo.ids.is_empty());
+
+    // Blocks sharing a class would overwrite it, and take turns being out
+    // of date in the cache, on every run.
+    let mut targets: HashMap<&PathBuf, &BlockInfo> = HashMap::new();
+
+    for binfo in blocks.iter() {
+        if let Some(other) = targets.insert(&binfo.target, binfo) {
+            return Err(format!(
+                "{:?} and {:?} both generate {:?}, give one of them another OUTPUT",
+                other.path, binfo.path, binfo.target));
+        }
+    }
+
     let model_files = paths.iter()
         .filter(|path| scan::filter_blockmodels(path))
         .collect::<Vec<&PathBuf>>();
@@ -363,179 +608,595 @@ fn automatic<P: AsRef<Path>>(path: P) -> Result<(), String> {
         .filter(|path| scan::filter_blockstates(path))
         .collect::<Vec<&PathBuf>>();
 
+    let bbmodel_files = paths.iter()
+        .filter(|path| path.extension() == Some("bbmodel".as_ref()))
+        .collect::<Vec<&PathBuf>>();
+
     let block_ids = {
         let mut ids = blocks.iter()
-            .map(|block| block.ids.iter())
-            .flatten()
+            .flat_map(|block| block.ids.iter())
             .cloned()
             .collect::<Vec<String>>();
 
+        ids.iter_mut().for_each(|id| *id = mcid_to_stem(id).to_string());
         ids.sort();
         ids.dedup();
 
         ids
     };
 
+    let assets = assets::Assets::load(
+        &settings.project_dir, &config.assets, config.client_assets.as_ref())?;
+
+    let blockstate_paths = by_stem(&blockstate_files);
+    let model_paths = by_stem(&model_files);
+    let bbmodel_paths = by_stem(&bbmodel_files);
+
+    // Look up by id in the project's files first, by stem like always, and
+    // then in the asset sources. Only for the blockstates' own models.
+    let read_blockstate = |id: &str| blockstate_paths.get(mcid_to_stem(id))
+        .and_then(|path| fs::read(path).ok())
+        .or_else(|| assets.read(&assets::blockstate_path(id)));
+
+    let read_model = |id: &str| model_paths.get(mcid_to_stem(id))
+        .and_then(|path| fs::read(path).ok())
+        .or_else(|| assets.read(&assets::model_path(id)));
+
+    // Other files, e.g. OBJ meshes, by their path in a resource pack.
+    let read_file = |asset_path: &str| paths.iter()
+        .find(|path| path.ends_with(asset_path))
+        .and_then(|path| fs::read(path).ok())
+        .or_else(|| assets.read(asset_path));
+
+    // Parents by their full id, by stem a model could be its own parent
+    // (e.g. "mymod:block/carpet" of "minecraft:block/carpet").
+    let read_parent = |id: &str| read_file(&assets::model_path(id));
+
     // Vec<blockstate, block id>
-    let blockstates: HashMap<String, Blockstate> = load_files(
+    let mut blockstates: HashMap<String, Blockstate> = load_files(
         &blockstate_files,
         |key| block_ids.iter().any(|id| id == key))?;
 
-    let models: HashMap<String, Model> = load_files(
+    for id in blocks.iter().flat_map(|block| block.ids.iter()) {
+        if blockstates.contains_key(mcid_to_stem(id)) {
+            continue;
+        }
+
+        if let Some(bytes) = assets.read(&assets::blockstate_path(id)) {
+            let blockstate = serde_json::from_slice(&bytes)
+                .map_err(|e| format!("{}: {:?}", assets::blockstate_path(id), e))?;
+
+            blockstates.insert(mcid_to_stem(id).to_string(), blockstate);
+        }
+    }
+
+    let mut models: HashMap<String, Model> = load_files(
         &model_files,
         |key| blockstates.values()
             .any(|state| state.variants.values()
-                 .any(|variant| key == mcid_to_stem(&variant.model))))?;
+                 .flat_map(|variant| variant.models())
+                 .any(|model| key == mcid_to_stem(model))))?;
+
+    let model_ids = blockstates.values()
+        .flat_map(|state| state.variants.values().flat_map(|variant| variant.models()))
+        .map(|model| model.to_string())
+        .collect::<HashSet<String>>();
+
+    // Blockbench projects are used instead of the json models exported
+    // from them, which may be out of date.
+    for id in model_ids.iter() {
+        if let Some(path) = bbmodel_paths.get(mcid_to_stem(id)) {
+            models.insert(mcid_to_stem(id).to_string(), bbmodel::load(path, &config.bbmodel)?);
+        }
+    }
 
-    for binfo in blocks.iter() {
-        let mut printed_fields = HashSet::new();
+    for id in model_ids.iter() {
+        if models.contains_key(mcid_to_stem(id)) {
+            continue;
+        }
+
+        if let Some(bytes) = assets.read(&assets::model_path(id)) {
+            let model = serde_json::from_slice(&bytes)
+                .map_err(|e| format!("{}: {:?}", assets::model_path(id), e))?;
+
+            models.insert(mcid_to_stem(id).to_string(), model);
+        }
+    }
+
+    assets::inherit_parents(&mut models, read_parent, read_file, &config.obj);
+
+    let settings_hash = {
+        let mut hasher = cache::Hasher::new();
+
+        hasher.write(VERSION.as_bytes());
+        hasher.write(&[settings.infer_ids as u8, settings.emit_kotlin as u8]);
+        hasher.write_file(fs::read(settings.project_dir.join(config::CONFIG_FILE)).ok());
+        hasher.write_file(fs::read(&overrides_path).ok());
+
+        hasher.finish()
+    };
 
-        let target = &binfo.target;
-        let target_package = binfo.package.clone();
-        let target_classname = binfo.classname.clone() + "BB";
+    // The Blockbench project in place of the json model, if there is one.
+    let read_source = |id: &str| bbmodel_paths.get(mcid_to_stem(id))
+        .and_then(|path| fs::read(path).ok())
+        .or_else(|| read_model(id));
 
-        // Ensure the package / directory exists.
-        if !binfo.target_next_to {
-            let parent = binfo.target.parent()
-                .ok_or(format!("Path doesn't contant a parent"))?;
+    let inputs = parallel::map(&blocks, |binfo| cache::block_inputs(
+        &settings_hash, &binfo.path, &binfo.ids, &blockstates,
+        read_blockstate, read_source, read_file));
 
-            fs::create_dir(parent.join("blockshape"))
-                .map_err(|e| format!("{:?}", e))?;
+    let mut old_cache = if settings.rebuild {
+        cache::Cache::new()
+    } else {
+        cache::load(&settings.project_dir)
+    };
+
+    let (fresh, stale): (Vec<_>, Vec<_>) = blocks.iter()
+        .zip(inputs)
+        .partition(|(binfo, inputs)| cache::is_up_to_date(
+            &old_cache, &settings.project_dir, &binfo.target, inputs));
+
+    // Only generated in parallel, nothing is written unless all blocks
+    // succeed.
+    let generated = parallel::map(&stale, |(binfo, _)| generate_block(
+        binfo, &config, &overrides, &blockstates, &models, &style))
+        .into_iter()
+        .collect::<Result<Vec<_>, String>>()?;
+
+    // Only the current blocks are kept, the others are gone.
+    let mut cache = cache::Cache::new();
+
+    for (binfo, _) in fresh.iter() {
+        let key = cache::key(&settings.project_dir, &binfo.target);
+
+        if let Some(entry) = old_cache.remove(&key) {
+            cache.insert(key, entry);
         }
+    }
 
-        let out_file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(&target)
-            .map_err(|e| format!("{:?}", e))?;
+    let mut failed = None;
 
-        let mut out: Box<dyn Write> = Box::new(BufWriter::new(out_file));
+    for ((binfo, inputs), block) in stale.into_iter().zip(generated) {
+        if let Err(e) = write_block(binfo, &block.source) {
+            failed = Some(e);
+            break;
+        }
 
-        write_header(&mut out, &target_package, &target_classname)
-            .map_err(|e| format!("{:?}", e))?;
+        report.extend(block.report);
+        ious.extend(block.ious);
 
-        // Iterate blockstates
-        for id in binfo.ids.iter() {
-            let blockstate = blockstates.get(id)
-                .ok_or(format!("This should be unreachable: {}", &id))?;
+        if let Some(output) = cache::output_hash(&binfo.target) {
+            cache.insert(
+                cache::key(&settings.project_dir, &binfo.target),
+                cache::Entry { inputs, output });
+        }
+    }
 
-            for (key, variant) in blockstate.variants.iter() {
-                let rotation = ModelRotation {
-                    x: variant.x.unwrap_or(0.0),
-                    y: variant.y.unwrap_or(0.0),
-                    z: variant.z.unwrap_or(0.0),
-                };
+    // What was written before the error is still up to date.
+    cache::save(&settings.project_dir, &cache)?;
 
-                let field_name = {
-                    let mut name = format!("{}", fieldify(id));
+    if let Some(e) = failed {
+        return Err(e);
+    }
 
-                    if 0.0 != rotation.x {
-                        name += &format!("_X{}", rotation.x as i32);
-                    }
+    println!("Generated {} classes ({} up to date).", blocks.len() - fresh.len(), fresh.len());
 
-                    if 0.0 != rotation.y {
-                        name += &format!("_Y{}", rotation.y as i32);
-                    }
+    if !ious.is_empty() {
+        let below = ious.iter().filter(|iou| **iou < config.metrics.warn_iou).count();
+
+        println!("Mean IoU {:.2} over {} shapes, {} below {:.2}.",
+                 ious.iter().sum::<f32>() / ious.len() as f32,
+                 ious.len(), below, config.metrics.warn_iou);
+    }
+
+    for line in report.iter() {
+        println!("  {}", line);
+    }
+
+    Ok(blocks.into_iter().map(|binfo| binfo.target).collect())
+}
+
+/// A block's class, not written yet.
+struct GeneratedBlock {
+    source: Vec<u8>,
+    /// The block's lines for the run report.
+    report: Vec<String>,
+    /// The IoU of each of its shapes.
+    ious: Vec<f32>,
+}
+
+/// Generate the source of a block's class.
+fn generate_block(binfo: &BlockInfo, config: &config::Config, overrides: &overrides::Overrides,
+                  blockstates: &HashMap<String, Blockstate>, models: &HashMap<String, Model>,
+                  style: &Style) -> Result<GeneratedBlock, String> {
+    let mut report = Vec::new();
+    let mut ious = Vec::new();
+
+    let mut printed_fields = HashSet::new();
+    let options = config.resolve(&binfo.options);
+    let kinds = config.shapes.kinds();
+
+    let target_package = binfo.package.clone();
+    let target_classname = binfo.target_classname.clone();
+
+    let mut out = Vec::new();
 
-                    if 0.0 != rotation.z {
-                        name += &format!("_Z{}", rotation.z as i32);
+    match binfo.target_language {
+        Language::Java => write_header(&mut out, &target_package, &target_classname),
+        Language::Kotlin => write_kotlin_header(&mut out, &target_package, &target_classname),
+    }.map_err(|e| format!("{:?}", e))?;
+
+    // Iterate blockstates
+    for id in binfo.ids.iter() {
+        let blockstate = blockstates.get(mcid_to_stem(id))
+            .ok_or(format!("This should be unreachable: {}", &id))?;
+
+        // Sorted, so the fields come out in the same order every run.
+        let mut variants = blockstate.variants.iter().collect::<Vec<_>>();
+        variants.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (key, variant) in variants {
+            if options.skip_variants.iter().any(|skip| variant_matches(key, skip)) {
+                continue;
+            }
+
+            if variant.z.is_some_and(|z| z != 0.0) {
+                eprintln!("Blockstates can't be rotated around z, ignored: {} [{}]", id, key);
+            }
+
+            let rotation = ModelRotation {
+                x: variant.x.unwrap_or(0.0),
+                y: variant.y.unwrap_or(0.0),
+            };
+
+            let field_name = {
+                let mut name = fieldify(id);
+
+                if 0.0 != rotation.x {
+                    name += &format!("_X{}", rotation.x as i32);
+                }
+
+                if 0.0 != rotation.y {
+                    name += &format!("_Y{}", rotation.y as i32);
+                }
+
+                while printed_fields.contains(&name) {
+                    if ends_with_variant_index(&name) {
+                        increment_variant_index(&mut name);
+                    } else {
+                        name += "_F0";
                     }
+                }
+
+                printed_fields.insert(name.clone());
+
+                name
+            };
+
+            let model = models.get(mcid_to_stem(&variant.model))
+                .ok_or(format!("This should be unreachable: {}", &variant.model))?;
+
+            let fallback = data::almost_full_cube();
+            let full_cube = data::cube();
 
-                    while printed_fields.contains(&name) {
-                        if ends_with_variant_index(&name) {
-                            increment_variant_index(&mut name);
-                        } else {
-                            name += "_F0";
-                        }
+            let elements = match (options.shape, model.elements.as_ref()) {
+                (Some(ShapeSource::FullCube), _) => full_cube.elements.as_ref().unwrap(),
+                (_, Some(els)) => els,
+                (_, None) => {
+                    if model.unsupported_loader().is_none() {
+                        eprintln!("No elements in model: {}", &variant.model);
                     }
 
-                    printed_fields.insert(name.clone());
+                    fallback.elements.as_ref().unwrap()
+                },
+            };
 
-                    name
+            if let (Some(loader), None) = (model.unsupported_loader(), options.shape) {
+                let what = if model.elements.is_some() {
+                    "used the model's own elements"
+                } else {
+                    "used the fallback shape"
                 };
 
-                let model = models.get(mcid_to_stem(&variant.model))
-                    .ok_or(format!("This should be unreachable: {}", &variant.model))?;
+                let line = format!(
+                    "{}: {}: unsupported model loader \"{}\", {}",
+                    target_classname, variant.model, loader, what);
 
-                let fallback = data::almost_full_cube();
+                // Every variant using the model would say the same.
+                if !report.contains(&line) {
+                    report.push(line);
+                }
+            }
+
+            // Forge submodels, with their own rotation and textures.
+            let mut parts = Vec::new();
+
+            for part in variant.parts.iter() {
+                let part_model = models.get(mcid_to_stem(&part.model))
+                    .ok_or(format!("This should be unreachable: {}", &part.model))?;
+
+                if part.z.is_some_and(|z| z != 0.0) {
+                    eprintln!("Blockstates can't be rotated around z, ignored: {} [{}]", id, key);
+                }
 
-                let elements = match model.elements.as_ref() {
-                    Some(els) => els,
-                    None => {
-                        eprintln!("{}", format!("No elements in model: {}", &variant.model));
+                let rotation = (part.x.unwrap_or(0.0), part.y.unwrap_or(0.0));
 
-                        &fallback.elements.as_ref().unwrap()
-                    },
+                parts.extend(part_model.elements.iter().flatten().map(|el| Element {
+                    state_rotation: Some(rotation),
+                    textures: el.textures.clone().or_else(|| part_model.textures.clone()),
+                    ..el.clone()
+                }));
+            }
+
+            let mut notes = Vec::new();
+            let elements = overrides::apply(
+                overrides, &config.elements, id, key,
+                elements, &parts, model.textures.as_ref(), &mut notes);
+
+            let comment = if notes.is_empty() {
+                key.to_string()
+            } else {
+                format!("{} ({})", key, notes.join(", "))
+            };
+
+            for (suffix, kind) in kinds.iter() {
+                let field_name = match suffix {
+                    Some(suffix) => format!("{}_{}", field_name, suffix),
+                    None => field_name.clone(),
                 };
 
-                let elements = elements.iter()
+                let textures = model.textures.as_ref();
+
+                let kept = elements.iter()
+                    .filter(|el| kind.elements.keeps(el, textures))
+                    .collect::<Vec<&Element>>();
+
+                let flat_policy = binfo.options.flat
+                    .or(kind.flat)
+                    .or(options.flat)
+                    .unwrap_or(FlatPolicy::Keep);
+
+                let mut flat_notes = Vec::new();
+                let kept = flat::apply(
+                    &kept, flat_policy, config.min_thickness.unwrap_or(1.0), &mut flat_notes);
+
+                // Every variant shares the model's elements, report them once.
+                for note in flat_notes {
+                    let line = format!("{}: {}: {}", target_classname, variant.model, note);
+
+                    if !report.contains(&line) {
+                        report.push(line);
+                    }
+                }
+
+                let elements = kept.iter()
                     .map(|el| approximate(el, &rotation))
+                    .map(|aabox| snap(aabox, &config.snap))
                     .collect::<Vec<AABox>>();
 
-                let elements = merging::merge_touching(&elements);
-
-                let visibility = "public";
+                // A block's own MERGE directive beats the shape kind's mode.
+                let merge_mode = binfo.options.merge
+                    .or(kind.merge)
+                    .or(options.merge)
+                    .unwrap_or(MergeMode::Touching);
+
+                let bounds_policy = binfo.options.bounds
+                    .or(kind.bounds)
+                    .or(options.bounds)
+                    .unwrap_or(BoundsPolicy::Keep);
+
+                let outside = elements.iter()
+                    .filter(|aabox| bounds::is_out_of_bounds(aabox))
+                    .count();
+
+                let groups = bounds::apply(&elements, bounds_policy);
+
+                if outside > 0 {
+                    let what = match bounds_policy {
+                        BoundsPolicy::Clamp => "clamped".to_string(),
+                        BoundsPolicy::Keep => "kept as is".to_string(),
+                        BoundsPolicy::Split => format!(
+                            "split into {} neighbor shapes",
+                            groups.len() - 1),
+                    };
+
+                    report.push(format!(
+                        "{}.{}: {} boxes outside the block, {}",
+                        target_classname, field_name, outside, what));
+                }
+
+                let mut shapes = Vec::new();
+
+                for (offset, elements) in groups.iter() {
+                    let field_name = field_name.clone() + &bounds::offset_suffix(*offset);
+
+                    let merged = merging::merge_with(elements, merge_mode);
+
+                    let max_boxes = binfo.options.max_boxes
+                        .or(kind.max_boxes)
+                        .or(options.max_boxes);
+
+                    let (elements, comment) = match max_boxes {
+                        Some(max_boxes) if merged.len() > max_boxes => {
+                            let simplified = merging::simplify(&merged, max_boxes);
+
+                            let exact = merging::union_volume(elements);
+                            let over = if exact > 0.0 {
+                                (merging::union_volume(&simplified) - exact) / exact * 100.0
+                            } else {
+                                0.0
+                            };
+
+                            report.push(format!(
+                                "{}.{}: simplified {} to {} boxes, {:.1}% over-coverage",
+                                target_classname, field_name, merged.len(), simplified.len(), over));
+
+                            let comment = append_note(&comment, &format!(
+                                "simplified to {} boxes, {:.1}% over-coverage",
+                                simplified.len(), over));
+
+                            (simplified, comment)
+                        },
+                        _ => (merged, comment.clone()),
+                    };
+
+                    shapes.push((*offset, field_name, comment, elements));
+                }
+
+                // Measured on the whole shape, neighbors' parts moved
+                // back next to the block.
+                let note = config.metrics.resolution.map(|resolution| {
+                    let solids = kept.iter()
+                        .filter_map(|el| metrics::Solid::from(&transform(el, &rotation)))
+                        .collect::<Vec<metrics::Solid>>();
+
+                    let boxes = shapes.iter()
+                        .flat_map(|(offset, _, _, boxes)| boxes.iter()
+                                  .map(move |aabox| bounds::translate(
+                                      aabox.clone(), (-offset.0, -offset.1, -offset.2))))
+                        .collect::<Vec<AABox>>();
+
+                    let accuracy = metrics::measure(&solids, &boxes, resolution);
+
+                    let note = format!(
+                        "model volume {:.1}, shape volume {:.1}, IoU {:.2}",
+                        accuracy.model_volume, accuracy.shape_volume, accuracy.iou);
+
+                    if accuracy.iou < config.metrics.warn_iou {
+                        report.push(format!(
+                            "{}.{}: IoU {:.2} below {:.2} ({})",
+                            target_classname, field_name, accuracy.iou,
+                            config.metrics.warn_iou, note));
+                    }
 
-                complex_write(
-                    &mut out,
-                    visibility,
-                    &field_name,
-                    Some(&key),
-                    &elements,
-                    &style,
-                    |aabox| format_cuboid_expr(aabox, &style)
-                ).map_err(|e| format!("{:?}", e))?;
+                    ious.push(accuracy.iou);
+
+                    note
+                });
+
+                for (i, (_, field_name, comment, elements)) in shapes.iter().enumerate() {
+                    // The block's own shape comes first.
+                    let comment = match (i, note.as_ref()) {
+                        (0, Some(note)) => append_note(comment, note),
+                        _ => comment.clone(),
+                    };
+
+                    let visibility = "public";
+
+                    match binfo.target_language {
+                        Language::Java => complex_write(
+                            &mut out,
+                            visibility,
+                            field_name,
+                            Some(&comment),
+                            elements,
+                            style,
+                            |aabox| format_cuboid_expr(aabox, style)
+                        ),
+                        Language::Kotlin => kotlin_complex_write(
+                            &mut out,
+                            field_name,
+                            Some(&comment),
+                            elements,
+                            style,
+                            |aabox| format_cuboid_expr(aabox, style)
+                        ),
+                    }.map_err(|e| format!("{:?}", e))?;
+                }
             }
         }
+    }
+
+    write_footer(&mut out)
+        .map_err(|e| format!("{:?}", e))?;
+
+    Ok(GeneratedBlock { source: out, report, ious })
+}
+
+/// Write a block's generated class to its target.
+fn write_block(binfo: &BlockInfo, source: &[u8]) -> Result<(), String> {
+    // Ensure the package / directory exists.
+    if !binfo.target_next_to {
+        let parent = binfo.target.parent()
+            .ok_or("Path doesn't contant a parent".to_string())?;
 
-        write_footer(&mut out)
+        // Blocks of the same package share it.
+        fs::create_dir_all(parent.join("blockshape"))
             .map_err(|e| format!("{:?}", e))?;
     }
 
-    Ok(())
+    let out_file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&binfo.target)
+        .map_err(|e| format!("{:?}", e))?;
+
+    let mut out = BufWriter::new(out_file);
+
+    out.write_all(source)
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("{:?}", e))
 }
 
-fn write_header(out: &mut Write, package: &str, classname: &str)
+fn write_header(out: &mut dyn Write, package: &str, classname: &str)
                    -> io::Result<()>
 {
     // "Header"
     writeln!(out, "package {};", package)?;
-    writeln!(out, "")?;
+    writeln!(out)?;
     writeln!(out, "// File generated by VoxelShape-Conv")?;
     writeln!(out, "//         Coded by Garophel")?;
-    writeln!(out, "")?;
+    writeln!(out)?;
     writeln!(out, "import net.minecraft.block.Block;")?;
     writeln!(out, "import net.minecraft.util.Util;")?;
     writeln!(out, "import net.minecraft.util.math.shapes.VoxelShape;")?;
     writeln!(out, "import net.minecraft.util.math.shapes.VoxelShapes;")?;
-    writeln!(out, "")?;
+    writeln!(out)?;
     writeln!(out, "public class {} {{", classname)?;
 
     Ok(())
 }
 
-fn write_footer(out: &mut Write) -> io::Result<()> {
+fn write_kotlin_header(out: &mut dyn Write, package: &str, classname: &str)
+                       -> io::Result<()>
+{
+    // "Header"
+    writeln!(out, "package {}", package)?;
+    writeln!(out)?;
+    writeln!(out, "// File generated by VoxelShape-Conv")?;
+    writeln!(out, "//         Coded by Garophel")?;
+    writeln!(out)?;
+    writeln!(out, "import net.minecraft.block.Block")?;
+    writeln!(out, "import net.minecraft.util.math.shapes.VoxelShape")?;
+    writeln!(out, "import net.minecraft.util.math.shapes.VoxelShapes")?;
+    writeln!(out)?;
+    writeln!(out, "object {} {{", classname)?;
+
+    Ok(())
+}
+
+fn write_footer(out: &mut dyn Write) -> io::Result<()> {
     // "Footer"
     writeln!(out, "}}")?;
     Ok(())
 }
 
 fn complex_write<F1>(
-    out: &mut Write,
+    out: &mut dyn Write,
     visibility: &str,
     field_name: &str,
     comment: Option<&str>,
-    vec: &Vec<AABox>,
+    vec: &[AABox],
     style: &Style,
     one: F1) -> io::Result<()>
 where F1: Fn(&AABox) -> String
 {
     // "Normal" indent level (inside public class).
-    let nindent = mkindent(1, &style);
+    let nindent = mkindent(1, style);
 
     // "Inside" indent level (inside of {}'s).
-    let iindent = mkindent(2, &style);
+    let iindent = mkindent(2, style);
 
     write!(
         out,
@@ -550,15 +1211,17 @@ where F1: Fn(&AABox) -> String
 
     let join = "VoxelShapes.or(";
 
-    if vec.len() == 1 {
+    if vec.is_empty() {
+        writeln!(out, "{}VoxelShape part = VoxelShapes.empty();", iindent)?;
+    } else if vec.len() == 1 {
         writeln!(out, "{}VoxelShape part = {};", iindent, one(&vec[0]))?;
     } else {
         writeln!(out, "{}VoxelShape part = {}", iindent, join)?;
         writeln!(out, "{}{},", iindent, one(&vec[0]))?;
 
-        for i in 1 .. vec.len() - 1 {
+        for aabox in &vec[1 .. vec.len() - 1] {
             write!(out, "{}{}", iindent, join)?;
-            writeln!(out, "{},", one(&vec[i]))?;
+            writeln!(out, "{},", one(aabox))?;
         }
 
         let closepars = ")".repeat(1.max(vec.len() - 1));
@@ -568,7 +1231,59 @@ where F1: Fn(&AABox) -> String
     writeln!(out, "{}return part;", iindent)?;
     writeln!(out, "{}}});", nindent)?;
 
-    writeln!(out, "")?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+// Kotlin variant of `complex_write`, uses the varargs `VoxelShapes.or`
+// instead of nesting the calls.
+fn kotlin_complex_write<F1>(
+    out: &mut dyn Write,
+    field_name: &str,
+    comment: Option<&str>,
+    vec: &[AABox],
+    style: &Style,
+    one: F1) -> io::Result<()>
+where F1: Fn(&AABox) -> String
+{
+    // "Normal" indent level (inside object).
+    let nindent = mkindent(1, style);
+
+    // "Inside" indent level (arguments of VoxelShapes.or).
+    let iindent = mkindent(2, style);
+
+    writeln!(out, "{}@JvmField", nindent)?;
+    write!(out, "{}val {}: VoxelShape = ", nindent, field_name)?;
+
+    if vec.len() <= 1 {
+        match vec.first() {
+            Some(aabox) => write!(out, "{}", one(aabox))?,
+            None => write!(out, "VoxelShapes.empty()")?,
+        }
+
+        if let Some(comment) = comment {
+            write!(out, " // {}", comment)?;
+        }
+
+        writeln!(out)?;
+    } else {
+        write!(out, "VoxelShapes.or(")?;
+
+        if let Some(comment) = comment {
+            write!(out, " // {}", comment)?;
+        }
+
+        writeln!(out)?;
+
+        for aabox in &vec[.. vec.len() - 1] {
+            writeln!(out, "{}{},", iindent, one(aabox))?;
+        }
+
+        writeln!(out, "{}{})", iindent, one(&vec[vec.len() - 1]))?;
+    }
+
+    writeln!(out)?;
 
     Ok(())
 }
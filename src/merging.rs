@@ -1,44 +1,386 @@
 use crate::AABox;
 
+use std::cmp::Reverse;
+use std::collections::{ BTreeSet, BinaryHeap };
+
+/// How the boxes of a single shape are combined before they are written.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeMode {
+    /// Touching boxes are replaced by their common bounding box.
+    Touching,
+    /// Boxes are cut apart so none overlap, then only boxes whose union is
+    /// exactly a box are merged.
+    Exact,
+    /// Boxes are written as they are.
+    None,
+    /// All boxes are replaced by their common bounding box, e.g. for an
+    /// outline around the whole model.
+    Hull,
+}
+
+impl MergeMode {
+    pub fn from(s: &str) -> Option<MergeMode> {
+        match s {
+            "touching" => Some(MergeMode::Touching),
+            "exact" => Some(MergeMode::Exact),
+            "none" => Some(MergeMode::None),
+            "hull" => Some(MergeMode::Hull),
+            _ => None,
+        }
+    }
+}
+
+pub fn merge_with(boxes: &[AABox], mode: MergeMode) -> Vec<AABox> {
+    match mode {
+        MergeMode::Touching => merge_touching(boxes),
+        MergeMode::Exact => merge_exact(&disjoint(boxes)),
+        MergeMode::None => boxes.to_vec(),
+        MergeMode::Hull => boxes.iter()
+            .cloned()
+            .reduce(merge)
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Replace touching boxes by their common bounding box until no boxes
+/// touch. The result, order included, is the same as merging the first
+/// touching pair over and over, like this function originally did.
+///
+/// First the boxes which end up merged together are found: each round
+/// finds the touching pairs with a sweep over x and joins them with
+/// union-find, rounds repeat as long as the grown boxes touch others. The
+/// pairwise merging is then replayed within each of these clusters only,
+/// since boxes of different clusters never touch.
 pub fn merge_touching(boxes: &[AABox]) -> Vec<AABox> {
-    let mut work: Vec<AABox> = Vec::new();
-    boxes.iter().for_each(|bx| work.push(bx.clone()));
+    let mut clusters = clusters(boxes).iter()
+        .map(|members| Cluster::new(boxes, members))
+        .collect::<Vec<Cluster>>();
+
+    // Each cluster with boxes left to merge, by the position of the box
+    // merged next. Only the stepped cluster's position changes.
+    let mut queue = clusters.iter()
+        .enumerate()
+        .filter_map(|(c, cluster)| cluster.next().map(|i| Reverse((cluster.positions[i], c))))
+        .collect::<BinaryHeap<Reverse<(Position, usize)>>>();
+
+    let mut made = 0;
+
+    while let Some(Reverse((_, c))) = queue.pop() {
+        let cluster = &mut clusters[c];
+
+        cluster.step((true, made));
+        made += 1;
+
+        if let Some(i) = cluster.next() {
+            queue.push(Reverse((cluster.positions[i], c)));
+        }
+    }
+
+    let mut merged = clusters.into_iter()
+        .flat_map(|cluster| cluster.into_boxes())
+        .collect::<Vec<(Position, AABox)>>();
+
+    merged.sort_by_key(|(position, _)| *position);
+    merged.into_iter().map(|(_, bx)| bx).collect()
+}
+
+// The input indices of the boxes which end up merged together.
+fn clusters(boxes: &[AABox]) -> Vec<Vec<usize>> {
+    // Each group's input boxes and their bounding box.
+    let mut groups = boxes.iter()
+        .cloned()
+        .enumerate()
+        .map(|(i, bx)| (vec![i], bx))
+        .collect::<Vec<(Vec<usize>, AABox)>>();
 
     loop {
-        let count = work.len();
-        let mut brek = false;
+        let count = groups.len();
+        let mut sets = UnionFind::new(count);
+        let mut joined = false;
 
-        for i in 0..count {
-            for j in 0..count {
-                if i == j {
-                    continue;
-                }
+        let mut order = (0..count).collect::<Vec<usize>>();
+        order.sort_by(|a, b| groups[*a].1 .0.partial_cmp(&groups[*b].1 .0).unwrap());
 
-                if touching(&work[i], &work[j]) {
-                    let (a, b) = if i < j {
-                        (work.remove(j), work.remove(i))
-                    } else {
-                        (work.remove(i), work.remove(j))
-                    };
+        // Boxes whose x range reaches the sweep position.
+        let mut active: Vec<usize> = Vec::new();
 
-                    work.push(merge(a, b));
+        for i in order {
+            let bx = &groups[i].1;
+            active.retain(|j| groups[*j].1 .3 >= bx.0);
 
-                    brek = true;
-                    break;
+            for j in active.iter() {
+                if touching(bx, &groups[*j].1) && sets.union(i, *j) {
+                    joined = true;
                 }
             }
 
-            if brek {
-                break;
+            active.push(i);
+        }
+
+        if !joined {
+            break;
+        }
+
+        let mut next: Vec<Option<(Vec<usize>, AABox)>> = vec![None; count];
+
+        for (i, (members, bx)) in groups.into_iter().enumerate() {
+            let root = sets.find(i);
+
+            next[root] = Some(match next[root].take() {
+                Some((mut members2, bx2)) => {
+                    members2.extend(members);
+                    (members2, merge(bx, bx2))
+                },
+                None => (members, bx),
+            });
+        }
+
+        groups = next.into_iter().flatten().collect();
+    }
+
+    groups.into_iter().map(|(members, _)| members).collect()
+}
+
+// Where a box is in the list the pairwise merging works on: the input
+// boxes in their order (false, index), then the merged boxes in the order
+// they were made (true, number).
+type Position = (bool, usize);
+
+// The pairwise merging of one cluster's boxes. The boxes are kept in the
+// order of their positions, the merged ones are made in that order too.
+struct Cluster {
+    positions: Vec<Position>,
+    boxes: Vec<AABox>,
+    alive: BTreeSet<usize>,
+    // The alive boxes which touch another one. A box never leaves it while
+    // alive, the box made of the one it touches contains that one.
+    ready: BTreeSet<usize>,
+    // The alive boxes which touch no other one.
+    lonely: Vec<usize>,
+}
+
+impl Cluster {
+    fn new(boxes: &[AABox], members: &[usize]) -> Cluster {
+        let mut members = members.to_vec();
+        members.sort_unstable();
+
+        let mut cluster = Cluster {
+            positions: members.iter().map(|i| (false, *i)).collect(),
+            boxes: members.iter().map(|i| boxes[*i].clone()).collect(),
+            alive: (0..members.len()).collect(),
+            ready: BTreeSet::new(),
+            lonely: Vec::new(),
+        };
+
+        for k in 0..members.len() {
+            cluster.sort_in(k);
+        }
+
+        cluster
+    }
+
+    // The first alive box (by position) the box touches. Usually one of
+    // the first, so the search ends early even when all boxes touch.
+    fn first_partner(&self, k: usize) -> Option<usize> {
+        self.alive.iter()
+            .copied()
+            .find(|j| *j != k && touching(&self.boxes[k], &self.boxes[*j]))
+    }
+
+    fn sort_in(&mut self, k: usize) {
+        if self.first_partner(k).is_some() {
+            self.ready.insert(k);
+        } else {
+            self.lonely.push(k);
+        }
+    }
+
+    // The first box which touches another, the next one to be merged.
+    fn next(&self) -> Option<usize> {
+        self.ready.iter().next().copied()
+    }
+
+    // Merge the next box with its partner, the merged box goes last.
+    fn step(&mut self, position: Position) {
+        let i = self.next().expect("nothing left to merge");
+        let j = self.first_partner(i).expect("merged box without a partner");
+
+        for k in [i, j].iter() {
+            self.alive.remove(k);
+            self.ready.remove(k);
+        }
+
+        let n = self.boxes.len();
+
+        self.boxes.push(merge(self.boxes[j].clone(), self.boxes[i].clone()));
+        self.positions.push(position);
+        self.alive.insert(n);
+
+        // Only the new box can give the lonely ones a partner.
+        let boxes = &self.boxes;
+        let (touched, lonely): (Vec<usize>, Vec<usize>) = self.lonely.iter()
+            .partition(|k| touching(&boxes[**k], &boxes[n]));
+
+        self.lonely = lonely;
+        self.ready.extend(touched);
+        self.sort_in(n);
+    }
+
+    fn into_boxes(self) -> Vec<(Position, AABox)> {
+        self.alive.iter()
+            .map(|k| (self.positions[*k], self.boxes[*k].clone()))
+            .collect()
+    }
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(count: usize) -> UnionFind {
+        UnionFind { parents: (0..count).collect() }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+
+        // Point the whole path at the root.
+        let mut i = i;
+
+        while self.parents[i] != root {
+            let parent = self.parents[i];
+            self.parents[i] = root;
+            i = parent;
+        }
+
+        root
+    }
+
+    // Whether a and b were in different sets.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+
+        if a != b {
+            self.parents[b] = a;
+        }
+
+        a != b
+    }
+}
+
+/// Like `merge_touching`, but only merges pairs of boxes which together
+/// form a box, so no volume is added to the shape.
+pub fn merge_exact(boxes: &[AABox]) -> Vec<AABox> {
+    let mut work = boxes.to_vec();
+
+    loop {
+        let count = work.len();
+        let mut found = None;
+
+        'outer: for i in 0..count {
+            for j in (i + 1)..count {
+                if exactly_mergeable(&work[i], &work[j]) {
+                    found = Some((i, j));
+                    break 'outer;
+                }
             }
         }
 
-        if !brek {
-            break work
+        match found {
+            Some((i, j)) => {
+                let b = work.remove(j);
+                let a = work.remove(i);
+
+                work.push(merge(a, b));
+            },
+            None => break work,
         }
     }
 }
 
+/// Boxes with the same union, but without overlaps. Boxes inside other
+/// boxes are dropped and the parts of boxes overlapping bigger ones are
+/// cut off.
+pub fn disjoint(boxes: &[AABox]) -> Vec<AABox> {
+    let mut sorted = boxes.to_vec();
+
+    // Bigger boxes first, so the smaller ones are the ones cut.
+    sorted.sort_by(|a, b| volume(b).partial_cmp(&volume(a)).unwrap());
+
+    let mut result: Vec<AABox> = Vec::new();
+
+    for bx in sorted {
+        let mut pieces = vec![bx];
+
+        for kept in result.iter() {
+            pieces = pieces.into_iter()
+                .flat_map(|piece| subtract(piece, kept))
+                .collect();
+        }
+
+        result.extend(pieces);
+    }
+
+    result
+}
+
+// The parts of a outside of b, as up to six boxes.
+fn subtract(a: AABox, b: &AABox) -> Vec<AABox> {
+    if contains(b, &a) {
+        return Vec::new();
+    }
+
+    let overlapping = a.0 < b.3 && b.0 < a.3 &&
+        a.1 < b.4 && b.1 < a.4 &&
+        a.2 < b.5 && b.2 < a.5;
+
+    if !overlapping {
+        return vec![a];
+    }
+
+    let mut pieces = Vec::new();
+    let mut rest = a;
+
+    // Cut off the slabs outside of b one axis at a time, what's left of
+    // the box in the end is inside b.
+    if rest.0 < b.0 {
+        pieces.push(AABox(rest.0, rest.1, rest.2, b.0, rest.4, rest.5));
+        rest.0 = b.0;
+    }
+
+    if rest.3 > b.3 {
+        pieces.push(AABox(b.3, rest.1, rest.2, rest.3, rest.4, rest.5));
+        rest.3 = b.3;
+    }
+
+    if rest.1 < b.1 {
+        pieces.push(AABox(rest.0, rest.1, rest.2, rest.3, b.1, rest.5));
+        rest.1 = b.1;
+    }
+
+    if rest.4 > b.4 {
+        pieces.push(AABox(rest.0, b.4, rest.2, rest.3, rest.4, rest.5));
+        rest.4 = b.4;
+    }
+
+    if rest.2 < b.2 {
+        pieces.push(AABox(rest.0, rest.1, rest.2, rest.3, rest.4, b.2));
+    }
+
+    if rest.5 > b.5 {
+        pieces.push(AABox(rest.0, rest.1, b.5, rest.3, rest.4, rest.5));
+    }
+
+    pieces
+}
+
 fn merge(mut a: AABox, b: AABox) -> AABox {
     a.0 = a.0.min(b.0);
     a.1 = a.1.min(b.1);
@@ -59,9 +401,251 @@ fn touching(a: &AABox, b: &AABox) -> bool {
     x_overlap && y_overlap && z_overlap
 }
 
+// Two boxes form a box together if one contains the other, or if they
+// span the same range on two axes and touch on the third.
+fn exactly_mergeable(a: &AABox, b: &AABox) -> bool {
+    if contains(a, b) || contains(b, a) {
+        return true;
+    }
+
+    let same_x = a.0 == b.0 && a.3 == b.3;
+    let same_y = a.1 == b.1 && a.4 == b.4;
+    let same_z = a.2 == b.2 && a.5 == b.5;
+
+    (same_y && same_z && axis_overlap(a.0, a.3, b.0, b.3)) ||
+        (same_x && same_z && axis_overlap(a.1, a.4, b.1, b.4)) ||
+        (same_x && same_y && axis_overlap(a.2, a.5, b.2, b.5))
+}
+
+fn contains(outer: &AABox, inner: &AABox) -> bool {
+    outer.0 <= inner.0 && outer.1 <= inner.1 && outer.2 <= inner.2 &&
+        outer.3 >= inner.3 && outer.4 >= inner.4 && outer.5 >= inner.5
+}
+
 fn axis_overlap(va0: f32, va1: f32, vb0: f32, vb1: f32) -> bool {
     vb0 >= va0 && vb0 <= va1 ||
         vb1 >= va0 && vb1 <= va1 ||
         va0 >= vb0 && va0 <= vb1 ||
         va1 >= vb0 && va1 <= vb1
 }
+
+/// Greedily merge the pair of boxes which adds the least volume, until
+/// there are at most `max_boxes` boxes left. Boxes swallowed by a merged
+/// box are dropped.
+pub fn simplify(boxes: &[AABox], max_boxes: usize) -> Vec<AABox> {
+    let mut work = boxes.to_vec();
+    let max_boxes = max_boxes.max(1);
+
+    while work.len() > max_boxes {
+        let mut best = None;
+
+        for i in 0..work.len() {
+            for j in (i + 1)..work.len() {
+                let added = added_volume(&work[i], &work[j]);
+
+                if best.is_none_or(|(_, _, best_added)| added < best_added) {
+                    best = Some((i, j, added));
+                }
+            }
+        }
+
+        let (i, j, _) = best.expect("at least two boxes");
+        let b = work.remove(j);
+        let a = work.remove(i);
+
+        let merged = merge(a, b);
+        work.retain(|bx| !contains(&merged, bx));
+        work.push(merged);
+    }
+
+    work
+}
+
+// Volume the bounding box of a and b covers, but a and b don't.
+fn added_volume(a: &AABox, b: &AABox) -> f32 {
+    let overlap = AABox(
+        a.0.max(b.0), a.1.max(b.1), a.2.max(b.2),
+        a.3.min(b.3), a.4.min(b.4), a.5.min(b.5));
+
+    let union = volume(a) + volume(b) - volume(&overlap);
+
+    volume(&merge(a.clone(), b.clone())) - union
+}
+
+fn volume(a: &AABox) -> f32 {
+    (a.3 - a.0).max(0.0) * (a.4 - a.1).max(0.0) * (a.5 - a.2).max(0.0)
+}
+
+/// Volume covered by the boxes, overlaps are counted once.
+pub fn union_volume(boxes: &[AABox]) -> f32 {
+    let mut xs = boxes.iter()
+        .flat_map(|bx| vec![bx.0, bx.3])
+        .collect::<Vec<f32>>();
+
+    xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    xs.dedup();
+
+    let mut total = 0.0;
+
+    // Sweep over the slabs between the boxes' x coordinates.
+    for x in xs.windows(2) {
+        let active = boxes.iter()
+            .filter(|bx| bx.0 <= x[0] && bx.3 >= x[1])
+            .collect::<Vec<&AABox>>();
+
+        total += union_area(&active) * (x[1] - x[0]);
+    }
+
+    total
+}
+
+// Area of the boxes' union in the y-z plane.
+fn union_area(boxes: &[&AABox]) -> f32 {
+    let mut ys = boxes.iter()
+        .flat_map(|bx| vec![bx.1, bx.4])
+        .collect::<Vec<f32>>();
+
+    ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    ys.dedup();
+
+    let mut total = 0.0;
+
+    for y in ys.windows(2) {
+        let mut zs = boxes.iter()
+            .filter(|bx| bx.1 <= y[0] && bx.4 >= y[1])
+            .map(|bx| (bx.2, bx.5))
+            .collect::<Vec<(f32, f32)>>();
+
+        zs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        // Length of the union of the z intervals.
+        let mut length = 0.0;
+        let mut end = f32::NEG_INFINITY;
+
+        for (z0, z1) in zs {
+            if z1 > end {
+                length += z1 - z0.max(end);
+                end = z1;
+            }
+        }
+
+        total += length * (y[1] - y[0]);
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ disjoint, merge, merge_touching, touching, union_volume };
+    use crate::AABox;
+
+    // The original `merge_touching`, which rescans all pairs after every
+    // merge.
+    fn merge_touching_reference(boxes: &[AABox]) -> Vec<AABox> {
+        let mut work: Vec<AABox> = Vec::new();
+        boxes.iter().for_each(|bx| work.push(bx.clone()));
+
+        loop {
+            let count = work.len();
+            let mut brek = false;
+
+            for i in 0..count {
+                for j in 0..count {
+                    if i == j {
+                        continue;
+                    }
+
+                    if touching(&work[i], &work[j]) {
+                        let (a, b) = if i < j {
+                            (work.remove(j), work.remove(i))
+                        } else {
+                            (work.remove(i), work.remove(j))
+                        };
+
+                        work.push(merge(a, b));
+
+                        brek = true;
+                        break;
+                    }
+                }
+
+                if brek {
+                    break;
+                }
+            }
+
+            if !brek {
+                break work
+            }
+        }
+    }
+
+    // Small boxes on the 1/16 pixel grid. With a `spread` of 5 they form
+    // clusters of all sizes, with 1 they nearly all touch.
+    fn random_boxes(count: usize, seed: u64, spread: f32) -> Vec<AABox> {
+        let mut state = seed;
+
+        let mut next = |max: f32| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+
+            ((state >> 33) % (max * 16.0) as u64) as f32 / 16.0
+        };
+
+        let side = spread * (count as f32).cbrt();
+
+        (0..count)
+            .map(|_| {
+                let (x, y, z) = (next(side), next(side), next(side));
+
+                AABox(x, y, z, x + 0.5 + next(2.5), y + 0.5 + next(2.5), z + 0.5 + next(2.5))
+            })
+            .collect()
+    }
+
+    fn corners(boxes: &[AABox]) -> Vec<[f32; 6]> {
+        boxes.iter()
+            .map(|bx| [bx.0, bx.1, bx.2, bx.3, bx.4, bx.5])
+            .collect()
+    }
+
+    #[test]
+    fn merge_touching_matches_the_reference() {
+        for spread in [5.0, 1.0].iter() {
+            for count in [0, 1, 2, 5, 10, 30, 100, 300].iter() {
+                for seed in 0..20 {
+                    let boxes = random_boxes(*count, seed, *spread);
+
+                    assert_eq!(
+                        corners(&merge_touching(&boxes)),
+                        corners(&merge_touching_reference(&boxes)),
+                        "{} boxes, seed {}, spread {}", count, seed, spread);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn disjoint_keeps_the_union_without_overlaps() {
+        for count in [1, 2, 5, 10, 30].iter() {
+            for seed in 0..20 {
+                let boxes = random_boxes(*count, seed, 5.0);
+                let pieces = disjoint(&boxes);
+
+                let (before, after) = (union_volume(&boxes), union_volume(&pieces));
+                assert!((before - after).abs() <= before * 1e-5,
+                        "{} boxes, seed {}: volume {} became {}", count, seed, before, after);
+
+                for (i, a) in pieces.iter().enumerate() {
+                    for b in pieces[i + 1..].iter() {
+                        let overlap = (a.3.min(b.3) - a.0.max(b.0)) > 0.0 &&
+                            (a.4.min(b.4) - a.1.max(b.1)) > 0.0 &&
+                            (a.5.min(b.5) - a.2.max(b.2)) > 0.0;
+
+                        assert!(!overlap, "{} boxes, seed {}: pieces overlap", count, seed);
+                    }
+                }
+            }
+        }
+    }
+}
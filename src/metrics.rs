@@ -0,0 +1,125 @@
+use crate::{ AABox, Vec3 };
+
+/// How well a shape's boxes match the model's elements, measured by
+/// sampling points on a grid.
+pub struct Accuracy {
+    /// Volume of the model's elements, rotated elements as they are.
+    pub model_volume: f32,
+    /// Volume of the generated boxes.
+    pub shape_volume: f32,
+    /// Intersection over union of the two.
+    pub iou: f32,
+}
+
+/// A rotated (and maybe rescaled) element: a parallelepiped spanned by
+/// three edges from a corner.
+pub struct Solid {
+    corner: Vec3,
+    // Maps a point relative to the corner onto the edges, the point is
+    // inside if all three coordinates are in 0..1.
+    inverse: [[f32; 3]; 3],
+    bounds: AABox,
+}
+
+impl Solid {
+    /// The solid of an element's corners, as returned by `transform`. Flat
+    /// elements have no volume and yield nothing.
+    pub fn from(verts: &[Vec3]) -> Option<Solid> {
+        let corner = verts[0];
+        let edges = [verts[1] - corner, verts[3] - corner, verts[4] - corner];
+
+        let inverse = invert([
+            [edges[0].0, edges[1].0, edges[2].0],
+            [edges[0].1, edges[1].1, edges[2].1],
+            [edges[0].2, edges[1].2, edges[2].2],
+        ])?;
+
+        let min = |f: fn(&Vec3) -> f32| verts.iter().map(f).fold(f32::INFINITY, f32::min);
+        let max = |f: fn(&Vec3) -> f32| verts.iter().map(f).fold(f32::NEG_INFINITY, f32::max);
+
+        let bounds = AABox(
+            min(|v| v.0), min(|v| v.1), min(|v| v.2),
+            max(|v| v.0), max(|v| v.1), max(|v| v.2));
+
+        Some(Solid { corner, inverse, bounds })
+    }
+
+    fn contains(&self, p: Vec3) -> bool {
+        let d = p - self.corner;
+        let m = &self.inverse;
+
+        (0..3).all(|i| {
+            let t = m[i][0] * d.0 + m[i][1] * d.1 + m[i][2] * d.2;
+            (0.0..=1.0).contains(&t)
+        })
+    }
+}
+
+/// Compare the solids with the boxes, sampling the centers of cubes of
+/// `resolution` pixels.
+pub fn measure(solids: &[Solid], boxes: &[AABox], resolution: f32) -> Accuracy {
+    let all = solids.iter().map(|s| &s.bounds).chain(boxes.iter());
+
+    let (mut lo, mut hi) = ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]);
+
+    for bx in all {
+        lo = [lo[0].min(bx.0), lo[1].min(bx.1), lo[2].min(bx.2)];
+        hi = [hi[0].max(bx.3), hi[1].max(bx.4), hi[2].max(bx.5)];
+    }
+
+    let steps = |axis: usize| ((hi[axis] - lo[axis]) / resolution).ceil().max(0.0) as usize;
+    let (nx, ny, nz) = (steps(0), steps(1), steps(2));
+
+    let (mut model, mut shape, mut both) = (0usize, 0usize, 0usize);
+
+    for ix in 0..nx {
+        for iy in 0..ny {
+            for iz in 0..nz {
+                let p = Vec3(
+                    lo[0] + (ix as f32 + 0.5) * resolution,
+                    lo[1] + (iy as f32 + 0.5) * resolution,
+                    lo[2] + (iz as f32 + 0.5) * resolution);
+
+                let in_model = solids.iter().any(|s| s.contains(p));
+                let in_shape = boxes.iter().any(|bx| {
+                    p.0 >= bx.0 && p.0 <= bx.3 &&
+                        p.1 >= bx.1 && p.1 <= bx.4 &&
+                        p.2 >= bx.2 && p.2 <= bx.5
+                });
+
+                model += in_model as usize;
+                shape += in_shape as usize;
+                both += (in_model && in_shape) as usize;
+            }
+        }
+    }
+
+    let cell = resolution * resolution * resolution;
+    let union = model + shape - both;
+
+    Accuracy {
+        model_volume: model as f32 * cell,
+        shape_volume: shape as f32 * cell,
+        // Nothing on either side is a perfect match.
+        iou: if union == 0 { 1.0 } else { both as f32 / union as f32 },
+    }
+}
+
+fn invert(m: [[f32; 3]; 3]) -> Option<[[f32; 3]; 3]> {
+    let det =
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) -
+        m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0]) +
+        m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+
+    if det.abs() < 1e-6 {
+        return None;
+    }
+
+    let c = |r0: usize, c0: usize, r1: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+
+    Some([
+        [c(1, 1, 2, 2) / det, -c(0, 1, 2, 2) / det, c(0, 1, 1, 2) / det],
+        [-c(1, 0, 2, 2) / det, c(0, 0, 2, 2) / det, -c(0, 0, 1, 2) / det],
+        [c(1, 0, 2, 1) / det, -c(0, 0, 2, 1) / det, c(0, 0, 1, 1) / det],
+    ])
+}
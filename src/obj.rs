@@ -0,0 +1,339 @@
+use crate::AABox;
+use crate::config::Obj;
+
+/// Turn an OBJ mesh into boxes: the mesh is voxelized within the block's
+/// 16³ space and the voxels are joined into as few boxes as fit. Vertices
+/// are in blocks (like Forge's OBJ loader reads them) and are moved by the
+/// configured offset after being scaled to pixels.
+///
+/// A voxel is filled if its center is inside the mesh, found by counting
+/// crossings along x, or if the mesh's surface passes through it, so open
+/// and thin meshes still have a shape.
+pub fn voxelize(source: &str, settings: &Obj) -> Result<Vec<AABox>, String> {
+    let (vertices, triangles) = parse(source, settings)?;
+
+    // A whole number of voxels across the block.
+    let n = (16.0 / settings.resolution.clamp(0.125, 16.0)).round() as usize;
+    let size = 16.0 / n as f32;
+
+    let mut grid = Grid { n, size, cells: vec![false; n * n * n] };
+
+    // Inside, by the parity of the crossings of a ray along x through each
+    // row of voxel centers. Slightly off center, so the rays don't run
+    // along the edges of axis-aligned meshes.
+    for iy in 0..n {
+        for iz in 0..n {
+            let y = (iy as f32 + 0.5) * size + 0.00013;
+            let z = (iz as f32 + 0.5) * size + 0.00031;
+
+            let mut crossings = triangles.iter()
+                .filter_map(|t| cross_x(&vertices[t[0]], &vertices[t[1]], &vertices[t[2]], y, z))
+                .collect::<Vec<f32>>();
+
+            crossings.sort_by(f32::total_cmp);
+
+            // An odd crossing left over is a hole in the mesh, ignored.
+            for pair in crossings.chunks_exact(2) {
+                for ix in 0..n {
+                    let x = (ix as f32 + 0.5) * size;
+
+                    if x >= pair[0] && x <= pair[1] {
+                        grid.fill(ix, iy, iz);
+                    }
+                }
+            }
+        }
+    }
+
+    // The surface, sampled at half the voxel size, for what has no inside
+    // (open or flat parts).
+    for t in triangles.iter() {
+        let (a, b, c) = (vertices[t[0]], vertices[t[1]], vertices[t[2]]);
+        let longest = distance(&a, &b).max(distance(&b, &c)).max(distance(&c, &a));
+        let steps = ((longest / (size / 2.0)).ceil() as usize).max(1);
+
+        for i in 0..=steps {
+            for j in 0..=(steps - i) {
+                let (u, v) = (i as f32 / steps as f32, j as f32 / steps as f32);
+
+                let p = [
+                    a[0] + (b[0] - a[0]) * u + (c[0] - a[0]) * v,
+                    a[1] + (b[1] - a[1]) * u + (c[1] - a[1]) * v,
+                    a[2] + (b[2] - a[2]) * u + (c[2] - a[2]) * v,
+                ];
+
+                grid.fill_surface(&p);
+            }
+        }
+    }
+
+    Ok(grid.into_boxes())
+}
+
+type Triangle = [usize; 3];
+
+// Vertices in pixels, and the faces split into triangles.
+fn parse(source: &str, settings: &Obj) -> Result<(Vec<[f32; 3]>, Vec<Triangle>), String> {
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("v") => {
+                let v = words.take(3)
+                    .map(|w| w.parse::<f32>())
+                    .collect::<Result<Vec<f32>, _>>()
+                    .map_err(|e| format!("line {}: {:?}", number + 1, e))?;
+
+                if v.len() != 3 {
+                    return Err(format!("line {}: vertex without 3 coordinates", number + 1));
+                }
+
+                // "nan" and "inf" parse, but aren't anywhere.
+                if v.iter().any(|c| !c.is_finite()) {
+                    return Err(format!("line {}: vertex not finite", number + 1));
+                }
+
+                vertices.push([
+                    v[0] * 16.0 + settings.offset[0],
+                    v[1] * 16.0 + settings.offset[1],
+                    v[2] * 16.0 + settings.offset[2],
+                ]);
+            },
+            Some("f") => {
+                // "f 1/1/1 2/2/1 3/3/1", only the vertex index is needed.
+                let face = words
+                    .map(|w| vertex_index(w, vertices.len()))
+                    .collect::<Option<Vec<usize>>>()
+                    .ok_or(format!("line {}: bad vertex index", number + 1))?;
+
+                // Polygons are split into a fan of triangles.
+                for i in 1..face.len().saturating_sub(1) {
+                    triangles.push([face[0], face[i], face[i + 1]]);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Ok((vertices, triangles))
+}
+
+// OBJ indices start at 1, negative ones count back from the last vertex.
+fn vertex_index(word: &str, count: usize) -> Option<usize> {
+    let index = word.split('/').next()?.parse::<i64>().ok()?;
+
+    let index = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+
+    if index >= 0 && (index as usize) < count {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+// Where a ray along x at (y, z) crosses the triangle, if it does.
+fn cross_x(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3], y: f32, z: f32) -> Option<f32> {
+    // Barycentric coordinates in the y-z plane.
+    let det = (b[1] - a[1]) * (c[2] - a[2]) - (c[1] - a[1]) * (b[2] - a[2]);
+
+    if det.abs() < 1e-9 {
+        return None;
+    }
+
+    let u = ((y - a[1]) * (c[2] - a[2]) - (c[1] - a[1]) * (z - a[2])) / det;
+    let v = ((b[1] - a[1]) * (z - a[2]) - (y - a[1]) * (b[2] - a[2])) / det;
+
+    if u < 0.0 || v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    Some(a[0] + (b[0] - a[0]) * u + (c[0] - a[0]) * v)
+}
+
+fn distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+struct Grid {
+    n: usize,
+    size: f32,
+    cells: Vec<bool>,
+}
+
+impl Grid {
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (y * self.n + z) * self.n + x
+    }
+
+    fn fill(&mut self, x: usize, y: usize, z: usize) {
+        let i = self.index(x, y, z);
+        self.cells[i] = true;
+    }
+
+    // Fill the voxel at a point on the surface, unless the point is on a
+    // filled voxel's face already. Points on the boundary between voxels
+    // belong to either one, the upper one is filled. Points outside of the
+    // block are left out.
+    fn fill_surface(&mut self, p: &[f32; 3]) {
+        let cells = |v: f32| {
+            let lower = (v / self.size - 0.001).floor();
+            let upper = (v / self.size + 0.001).floor();
+
+            [upper, lower].iter()
+                .filter(|i| **i >= 0.0 && (**i as usize) < self.n)
+                .map(|i| *i as usize)
+                .collect::<Vec<usize>>()
+        };
+
+        let (xs, ys, zs) = (cells(p[0]), cells(p[1]), cells(p[2]));
+
+        let filled = xs.iter().any(|x| ys.iter().any(|y| zs.iter().any(|z| self.get(*x, *y, *z))));
+
+        if let (false, Some(x), Some(y), Some(z)) = (filled, xs.first(), ys.first(), zs.first()) {
+            self.fill(*x, *y, *z);
+        }
+    }
+
+    fn get(&self, x: usize, y: usize, z: usize) -> bool {
+        self.cells[self.index(x, y, z)]
+    }
+
+    // Grow a box from each voxel not yet in one: along x, then z, then y,
+    // as far as all voxels are filled.
+    fn into_boxes(mut self) -> Vec<AABox> {
+        let n = self.n;
+        let mut boxes = Vec::new();
+
+        for y in 0..n {
+            for z in 0..n {
+                for x in 0..n {
+                    if !self.get(x, y, z) {
+                        continue;
+                    }
+
+                    let mut x1 = x + 1;
+
+                    while x1 < n && self.get(x1, y, z) {
+                        x1 += 1;
+                    }
+
+                    let mut z1 = z + 1;
+
+                    while z1 < n && (x..x1).all(|x| self.get(x, y, z1)) {
+                        z1 += 1;
+                    }
+
+                    let mut y1 = y + 1;
+
+                    while y1 < n && (z..z1).all(|z| (x..x1).all(|x| self.get(x, y1, z))) {
+                        y1 += 1;
+                    }
+
+                    // Taken, so the next boxes don't overlap this one.
+                    for yy in y..y1 {
+                        for zz in z..z1 {
+                            for xx in x..x1 {
+                                let i = self.index(xx, yy, zz);
+                                self.cells[i] = false;
+                            }
+                        }
+                    }
+
+                    let s = self.size;
+
+                    boxes.push(AABox(
+                        x as f32 * s, y as f32 * s, z as f32 * s,
+                        x1 as f32 * s, y1 as f32 * s, z1 as f32 * s));
+                }
+            }
+        }
+
+        boxes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ parse, voxelize };
+    use crate::config::Obj;
+
+    const CUBE: &str = "
+v 0.25 0.25 0.25
+v 0.75 0.25 0.25
+v 0.75 0.75 0.25
+v 0.25 0.75 0.25
+v 0.25 0.25 0.75
+v 0.75 0.25 0.75
+v 0.75 0.75 0.75
+v 0.25 0.75 0.75
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+
+    fn settings() -> Obj {
+        Obj { resolution: 1.0, offset: [0.0; 3] }
+    }
+
+    #[test]
+    fn parse_negative_indices() {
+        let (vertices, triangles) = parse(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3/1/1 -2/2/1 -1/3/1\n", &settings()).unwrap();
+
+        assert_eq!(vertices[1], [16.0, 0.0, 0.0]);
+        assert_eq!(triangles, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn parse_polygons() {
+        let (_, triangles) = parse(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 2 0\nf 1 2 3 4 5\n", &settings()).unwrap();
+
+        assert_eq!(triangles, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn parse_bad_lines() {
+        let settings = settings();
+
+        assert!(parse("v 0 0\n", &settings).is_err());
+        assert!(parse("v 0 x 0\n", &settings).is_err());
+        assert!(parse("v 0 nan 0\n", &settings).is_err());
+        assert!(parse("v 0 0 inf\n", &settings).is_err());
+        assert!(parse("v 0 0 0\nf 1 2 3\n", &settings).is_err());
+        assert!(parse("v 0 0 0\nf 0 1 1\n", &settings).is_err());
+
+        // Anything else is left alone.
+        assert!(parse("# a cube\no cube\nvt 0 0\nusemtl stone\n", &settings).is_ok());
+    }
+
+    #[test]
+    fn voxelize_cube() {
+        let boxes = voxelize(CUBE, &settings()).unwrap();
+
+        assert_eq!(boxes.len(), 1);
+
+        let bx = &boxes[0];
+        assert_eq!([bx.0, bx.1, bx.2, bx.3, bx.4, bx.5], [4.0, 4.0, 4.0, 12.0, 12.0, 12.0]);
+    }
+
+    #[test]
+    fn voxelize_uneven_resolution() {
+        // 16 / 3 rounds to 5 voxels across, 3.2 pixels each.
+        let settings = Obj { resolution: 3.0, offset: [0.0; 3] };
+        let boxes = voxelize("v 0 0 0\nv 1 0 0\nv 1 0 1\nv 0 0 1\nf 1 2 3 4\n", &settings).unwrap();
+
+        assert_eq!(boxes.len(), 1);
+        assert_eq!(boxes[0].3, 16.0);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::data::Element;
+use crate::rules::ElementRules;
+use crate::{ mcid_to_stem, variant_matches };
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+pub static OVERRIDES_FILE: &str = "voxelshape-overrides.json";
+
+/// Hand-tuned shapes for blocks whose model isn't their collision shape,
+/// read from `voxelshape-overrides.json`. Keyed by block id, e.g.
+///
+/// ```json
+/// { "mymod:grate": {
+///     "exclude": [0, "bars"],
+///     "add": [[0, 0, 0, 16, 1, 16]],
+///     "variants": { "open=true": { "boxes": [[0, 0, 0, 16, 2, 16]] } }
+/// } }
+/// ```
+///
+/// All boxes are in model coordinates and get rotated like the model's
+/// elements.
+pub type Overrides = HashMap<String, BlockOverride>;
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct BlockOverride {
+    #[serde(flatten)]
+    pub all: Override,
+    /// Keyed by a variant pattern, e.g. "facing=north" or "open=true".
+    pub variants: HashMap<String, Override>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Override {
+    /// Replaces the model's elements.
+    pub boxes: Option<Vec<[f32; 6]>>,
+    /// Added to the model's elements.
+    pub add: Vec<[f32; 6]>,
+    /// Model elements to leave out, by index (in the variant's model) or
+    /// name.
+    pub exclude: Vec<ElementRef>,
+    /// Rules applied on top of the project's element rules.
+    pub elements: ElementRules,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ElementRef {
+    Index(usize),
+    Name(String),
+}
+
+impl ElementRef {
+    // Indices are those of the variant's model, `own` elements long.
+    fn matches(&self, index: usize, own: usize, el: &Element) -> bool {
+        match self {
+            ElementRef::Index(i) => *i == index && index < own,
+            ElementRef::Name(name) => el.name.as_ref() == Some(name),
+        }
+    }
+}
+
+/// Apply the element rules and the overrides of a block's variant to the
+/// model's elements and those of its parts (Forge submodels), which come
+/// after them. What was changed is appended to `notes`, so it can be
+/// reported.
+#[allow(clippy::too_many_arguments)]
+pub fn apply(overrides: &Overrides, rules: &ElementRules, id: &str, key: &str,
+             elements: &[Element], parts: &[Element],
+             textures: Option<&HashMap<String, String>>,
+             notes: &mut Vec<String>) -> Vec<Element> {
+    let found = overrides.iter()
+        .filter(|(block, _)| mcid_to_stem(block) == mcid_to_stem(id))
+        .flat_map(|(_, block)| {
+            let variants = block.variants.iter()
+                .filter(|(pattern, _)| variant_matches(key, pattern))
+                .map(|(_, ovr)| ovr);
+
+            Some(&block.all).into_iter().chain(variants)
+        })
+        .collect::<Vec<&Override>>();
+
+    let own = elements.len();
+    let elements = elements.iter().chain(parts.iter()).collect::<Vec<&Element>>();
+
+    let kept = elements.iter()
+        .map(|el| rules.keeps(el, textures) &&
+             found.iter().all(|ovr| ovr.elements.keeps(el, textures)))
+        .collect::<Vec<bool>>();
+
+    let filtered = kept.iter().filter(|keep| !**keep).count();
+
+    if filtered > 0 {
+        notes.push(format!("filtered elements: {}", filtered));
+    }
+
+    let mut result = elements.iter()
+        .enumerate()
+        .filter(|(i, _)| kept[*i])
+        .filter(|(i, el)| !found.iter()
+                .any(|ovr| ovr.exclude.iter().any(|ex| ex.matches(*i, own, el))))
+        .map(|(_, el)| (*el).clone())
+        .collect::<Vec<Element>>();
+
+    let excluded = elements.len() - filtered - result.len();
+
+    if excluded > 0 {
+        notes.push(format!("override excluded elements: {}", excluded));
+    }
+
+    // Variant overrides come last, so their boxes win.
+    if let Some(boxes) = found.iter().rev().find_map(|ovr| ovr.boxes.as_ref()) {
+        result = boxes.iter().map(to_element).collect();
+        notes.push(format!("override boxes: {}", boxes.len()));
+    }
+
+    let added = found.iter()
+        .flat_map(|ovr| ovr.add.iter())
+        .map(to_element)
+        .collect::<Vec<Element>>();
+
+    if !added.is_empty() {
+        notes.push(format!("override added boxes: {}", added.len()));
+    }
+
+    result.extend(added);
+    result
+}
+
+fn to_element(bx: &[f32; 6]) -> Element {
+    Element::from_box(bx[..3].to_vec(), bx[3..].to_vec())
+}
+
+/// Load the overrides file, or nothing if there is none.
+pub fn load(path: &Path) -> Result<Overrides, String> {
+    if !path.is_file() {
+        return Ok(Overrides::new());
+    }
+
+    let file = File::open(path)
+        .map_err(|e| format!("{:?}", e))?;
+
+    serde_json::from_reader(file)
+        .map_err(|e| format!("{:?}: {:?}", path, e))
+}
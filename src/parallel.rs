@@ -0,0 +1,27 @@
+use std::thread;
+
+/// Map the items on all cores. The results are in the items' order, so
+/// anything built from them is the same as with a sequential map.
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where T: Sync, R: Send, F: Fn(&T) -> R + Sync {
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    if threads == 1 || items.len() < 2 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk_size = items.len().div_ceil(threads);
+    let f = &f;
+
+    thread::scope(|scope| {
+        let handles = items.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<R>>()))
+            .collect::<Vec<_>>();
+
+        handles.into_iter()
+            .flat_map(|handle| handle.join().expect("worker thread panicked"))
+            .collect()
+    })
+}
@@ -0,0 +1,128 @@
+use crate::data::Element;
+
+use std::collections::HashMap;
+
+/// Include/exclude rules for model elements, applied before the elements
+/// are approximated. An element is kept if it matches any `include` rule
+/// (or there are none) and no `exclude` rule, e.g.
+/// `{ "exclude": [{ "name": "leaves*" }, { "texture": "#glow" }] }`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ElementRules {
+    pub include: Vec<Rule>,
+    pub exclude: Vec<Rule>,
+}
+
+/// Globs (`*` and `?`) matched against an element. All given fields have
+/// to match.
+#[derive(Clone, Deserialize)]
+pub struct Rule {
+    /// The element's name, as exported by Blockbench.
+    pub name: Option<String>,
+    /// A texture used by one of the element's faces. Either a texture
+    /// variable ("#chain") or a texture path ("mymod:block/chain").
+    pub texture: Option<String>,
+}
+
+impl ElementRules {
+    pub fn keeps(&self, el: &Element, textures: Option<&HashMap<String, String>>) -> bool {
+        let included = self.include.is_empty() ||
+            self.include.iter().any(|rule| rule.matches(el, textures));
+
+        included && !self.exclude.iter().any(|rule| rule.matches(el, textures))
+    }
+}
+
+impl Rule {
+    fn matches(&self, el: &Element, textures: Option<&HashMap<String, String>>) -> bool {
+        let textures = el.textures.as_ref().or(textures);
+
+        let name_matches = match &self.name {
+            Some(pattern) => el.name.as_ref().is_some_and(|name| glob(pattern, name)),
+            None => true,
+        };
+
+        let texture_matches = match &self.texture {
+            Some(pattern) => face_textures(el).iter()
+                .any(|var| texture_matches(pattern, var, textures)),
+            None => true,
+        };
+
+        name_matches && texture_matches
+    }
+}
+
+fn face_textures(el: &Element) -> Vec<&str> {
+    let faces = match &el.faces {
+        Some(faces) => faces,
+        None => return Vec::new(),
+    };
+
+    [&faces.down, &faces.up, &faces.north, &faces.south, &faces.west, &faces.east]
+        .iter()
+        .filter_map(|face| face.as_ref())
+        .map(|face| face.texture.as_str())
+        .collect()
+}
+
+fn texture_matches(pattern: &str, var: &str, textures: Option<&HashMap<String, String>>) -> bool {
+    if pattern.starts_with('#') {
+        return glob(pattern, var);
+    }
+
+    let path = match resolve_texture(var, textures) {
+        Some(path) => path,
+        None => return false,
+    };
+
+    // "block/chain" is short for "minecraft:block/chain".
+    if pattern.contains(':') && !path.contains(':') {
+        glob(pattern, &format!("minecraft:{}", path))
+    } else {
+        glob(pattern, path)
+    }
+}
+
+// Follow "#var" references through the model's textures.
+fn resolve_texture<'a>(var: &'a str, textures: Option<&'a HashMap<String, String>>) -> Option<&'a str> {
+    let mut current = var;
+
+    // Bounded, in case the variables reference each other.
+    for _ in 0..16 {
+        if !current.starts_with('#') {
+            return Some(current);
+        }
+
+        current = textures?.get(&current[1..])?;
+    }
+
+    None
+}
+
+/// Match a string against a glob pattern with `*` and `?` wildcards.
+pub fn glob(pattern: &str, s: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let s = s.chars().collect::<Vec<char>>();
+
+    let (mut p, mut i) = (0, 0);
+    let mut star = None;
+
+    while i < s.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == s[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, i));
+            p += 1;
+        } else if let Some((star_p, star_i)) = star {
+            // Let the last '*' eat one more character.
+            p = star_p + 1;
+            i = star_i + 1;
+            star = Some((star_p, star_i + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -1,5 +1,11 @@
+use crate::bounds::BoundsPolicy;
+use crate::flat::FlatPolicy;
+use crate::merging::MergeMode;
+use crate::parallel;
+
+use std::collections::HashMap;
 use std::fmt;
-use std::fs::{ File };
+use std::fs::{ self, File };
 use std::io::{ self, BufReader, BufRead };
 use std::path::{ Path, PathBuf };
 
@@ -7,7 +13,7 @@ use std::path::{ Path, PathBuf };
 // put their source files there.
 // NOTE: only at the root of the project.
 fn blacklist_dirs() -> Vec<String> {
-    vec![
+    [
         // Version control
         ".git",
         ".hg",
@@ -46,20 +52,18 @@ pub fn discover_files(project_dir: &Path) -> Result<Vec<PathBuf>, String> {
         Ok(ents) => ents,
     };
 
-    for ent in ents {
-        if let Ok(ent) = ent {
-            let blacklisted = blacklist.iter()
-                .any(|bl_dir| bl_dir.as_str() == ent.file_name());
+    for ent in ents.flatten() {
+        let blacklisted = blacklist.iter()
+            .any(|bl_dir| bl_dir.as_str() == ent.file_name());
 
-            if blacklisted {
-                continue;
-            }
+        if blacklisted {
+            continue;
+        }
 
-            let path = ent.path();
+        let path = ent.path();
 
-            if path.is_dir() {
-                discover_files_rc(path, &mut discovered_files)?;
-            }
+        if path.is_dir() {
+            discover_files_rc(path, &mut discovered_files)?;
         }
     }
 
@@ -77,26 +81,29 @@ fn discover_files_rc(base: PathBuf, output: &mut Vec<PathBuf>) -> Result<(), Str
         Ok(ents) => ents,
     };
 
-    for ent in ents {
-        if let Ok(ent) = ent {
-            let path = ent.path();
+    for ent in ents.flatten() {
+        let path = ent.path();
 
-            if path.is_dir() {
-                discover_files_rc(path, output)?;
-            } else {
-                output.push(path);
-            }
+        if path.is_dir() {
+            discover_files_rc(path, output)?;
+        } else {
+            output.push(path);
         }
     }
 
     Ok(())
 }
 
-pub fn filter_blockmodels(path: &PathBuf) -> bool {
+pub fn filter_blockmodels(path: &Path) -> bool {
     filter_blockmodels_impl(path).unwrap_or(false)
 }
 
-fn filter_blockmodels_impl(path: &PathBuf) -> Option<bool> {
+fn filter_blockmodels_impl(path: &Path) -> Option<bool> {
+    // OBJ meshes and such live next to the models.
+    if path.extension()? != "json" {
+        return Some(false);
+    }
+
     let parent = path.parent()?;
     let parent_fn = parent.file_name()?;
 
@@ -107,26 +114,83 @@ fn filter_blockmodels_impl(path: &PathBuf) -> Option<bool> {
     let grandparent = parent.parent()?;
     let grandparent_fn = grandparent.file_name()?;
 
-    Some(if grandparent_fn == "models" {
-        true
-    } else {
-        false
-    })
+    Some(grandparent_fn == "models")
 }
 
-pub fn filter_blockstates(path: &PathBuf) -> bool {
+pub fn filter_blockstates(path: &Path) -> bool {
     filter_blockstates_impl(path).unwrap_or(false)
 }
 
-fn filter_blockstates_impl(path: &PathBuf) -> Option<bool> {
+fn filter_blockstates_impl(path: &Path) -> Option<bool> {
+    if path.extension()? != "json" {
+        return Some(false);
+    }
+
     let parent = path.parent()?;
     let parent_fn = parent.file_name()?;
 
-    Some(if parent_fn == "blockstates" {
-        true
-    } else {
-        false
-    })
+    Some(parent_fn == "blockstates")
+}
+
+/// Language of a block's source file or of the generated class.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    Java,
+    Kotlin,
+}
+
+impl Language {
+    pub fn from_path(path: &Path) -> Option<Language> {
+        match path.extension()?.to_str()? {
+            "java" => Some(Language::Java),
+            "kt" => Some(Language::Kotlin),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Language::Java => "java",
+            Language::Kotlin => "kt",
+        }
+    }
+}
+
+/// Where the boxes of a block's shapes come from.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShapeSource {
+    /// The elements of the variant's model.
+    Model,
+    /// A full 16x16x16 cube, regardless of the model.
+    FullCube,
+}
+
+impl ShapeSource {
+    pub fn from(s: &str) -> Option<ShapeSource> {
+        match s {
+            "model" => Some(ShapeSource::Model),
+            "full_cube" => Some(ShapeSource::FullCube),
+            _ => None,
+        }
+    }
+}
+
+/// Per-block settings read from the java source, e.g. from the
+/// parameters of a `@VoxelShapeSource` annotation or `VSC!` directives.
+/// Unset options fall back to the project defaults (see config.rs).
+#[derive(Clone, Default)]
+pub struct BlockOptions {
+    /// Name of the generated class (default = classname + "BB").
+    pub output: Option<String>,
+    pub merge: Option<MergeMode>,
+    /// Variants matching any of these (e.g. "powered=true") are not generated.
+    pub skip_variants: Vec<String>,
+    pub shape: Option<ShapeSource>,
+    pub bounds: Option<BoundsPolicy>,
+    pub flat: Option<FlatPolicy>,
+    /// Shapes with more boxes are simplified, see `merging::simplify`.
+    pub max_boxes: Option<usize>,
 }
 
 pub struct BlockInfo {
@@ -134,7 +198,11 @@ pub struct BlockInfo {
     pub package: String,
     pub classname: String,
     pub ids: Vec<String>,
+    pub options: BlockOptions,
+    pub language: Language,
     pub target: PathBuf,
+    pub target_classname: String,
+    pub target_language: Language,
     pub target_new: bool,
     pub target_next_to: bool,
 }
@@ -154,7 +222,34 @@ impl fmt::Display for BlockInfo {
             writeln!(f, "ids:")?;
         }
 
-        writeln!(f, "target: {:?} ({}) ({})", self.target,
+        if let Some(merge) = self.options.merge {
+            writeln!(f, "merge: {:?}", merge)?;
+        }
+
+        if let Some(shape) = self.options.shape {
+            writeln!(f, "shape: {:?}", shape)?;
+        }
+
+        if let Some(bounds) = self.options.bounds {
+            writeln!(f, "bounds: {:?}", bounds)?;
+        }
+
+        if let Some(flat) = self.options.flat {
+            writeln!(f, "flat: {:?}", flat)?;
+        }
+
+        if let Some(max_boxes) = self.options.max_boxes {
+            writeln!(f, "max boxes: {}", max_boxes)?;
+        }
+
+        if !self.options.skip_variants.is_empty() {
+            writeln!(f, "skip variants: {}", self.options.skip_variants.join(", "))?;
+        }
+
+        writeln!(f, "language: {:?}", self.language)?;
+
+        writeln!(f, "target: {:?} [{} ({:?})] ({}) ({})", self.target,
+                 self.target_classname, self.target_language,
                  if self.target_new {
                      "create"
                  } else {
@@ -184,14 +279,15 @@ fn package_into_vec(package: &str) -> Vec<String> {
     stack
 }
 
-fn find_bb_target(path: &Path, classname: &str, prefer_blockshape_package: bool) -> Option<(bool, PathBuf)> {
+fn find_bb_target(path: &Path, target_classname: &str, target_language: Language,
+                  prefer_blockshape_package: bool) -> Option<(bool, PathBuf)> {
     let parent = path.parent()?;
 
     if !parent.is_dir() {
         return None;
     }
 
-    let next_to = parent.join(format!("{}BB.java", classname));
+    let next_to = parent.join(format!("{}.{}", target_classname, target_language.extension()));
 
     if !prefer_blockshape_package {
         // Next_to is preferred.
@@ -211,25 +307,46 @@ fn find_bb_target(path: &Path, classname: &str, prefer_blockshape_package: bool)
     Some((false, blockshape_package))
 }
 
-pub fn process_java_file(path: &Path) -> io::Result<BlockInfo> {
-    let processors = java_line_processors();
+/// Read the block markers from a java or kotlin source file. Blocks found
+/// in kotlin sources get a kotlin target if `emit_kotlin` is set.
+pub fn process_source_file(path: &Path, emit_kotlin: bool) -> io::Result<BlockInfo> {
+    let language = Language::from_path(path).ok_or(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "Not a java or kotlin source file"))?;
+
+    let processors = match language {
+        Language::Java => java_line_processors(),
+        Language::Kotlin => kotlin_line_processors(),
+    };
+
     let mut ids = Vec::new();
+    let mut options = BlockOptions::default();
 
     let file = File::open(path)?;
     let reader = BufReader::new(file);
 
     let mut package = None;
     let mut classname = None;
+    let mut pending = None;
 
     for line in reader.lines() {
-        let line = line?;
+        let line = match join_annotation(&mut pending, line?) {
+            Some(line) => line,
+            None => continue,
+        };
 
         if package.is_none() {
-            java_package_find(&line, &mut package);
+            match language {
+                Language::Java => java_package_find(&line, &mut package),
+                Language::Kotlin => kotlin_package_find(&line, &mut package),
+            };
         }
 
         if classname.is_none() {
-            java_classname_find(&line, &mut classname);
+            match language {
+                Language::Java => java_classname_find(&line, &mut classname),
+                Language::Kotlin => kotlin_classname_find(&line, &mut classname),
+            };
         }
 
         for proc in processors.iter() {
@@ -237,19 +354,33 @@ pub fn process_java_file(path: &Path) -> io::Result<BlockInfo> {
                 ids.append(new_ids);
             }
         }
+
+        java_annotation_options(&line, &mut options);
+        java_comment_options(&line, &mut options);
+    }
+
+    if pending.is_some() {
+        eprintln!("Unclosed {} in {:?}", ANNOTATION, path);
     }
 
     let package = package.ok_or(io::Error::new(
         io::ErrorKind::InvalidData,
-        "Unable to find package from java file"))?;
+        "Unable to find package from source file"))?;
 
     let classname = classname.ok_or(io::Error::new(
         io::ErrorKind::InvalidData,
-        "Unable to find classname from java file"))?;
+        "Unable to find classname from source file"))?;
+
+    let target_classname = options.output.clone()
+        .unwrap_or_else(|| classname.clone() + "BB");
+
+    let target_language = match language {
+        Language::Kotlin if emit_kotlin => Language::Kotlin,
+        _ => Language::Java,
+    };
 
-    let (next_to, target) = find_bb_target(path, &classname, false)
-        .ok_or(io::Error::new(
-            io::ErrorKind::Other,
+    let (next_to, target) = find_bb_target(path, &target_classname, target_language, false)
+        .ok_or(io::Error::other(
             "Unable to find target file for VoxelShape bounding box"))?;
 
     let package = if next_to {
@@ -257,7 +388,7 @@ pub fn process_java_file(path: &Path) -> io::Result<BlockInfo> {
     } else {
         let mut parts = package_into_vec(&package);
 
-        if parts.last().map_or(false, |last| last.starts_with("block")) {
+        if parts.last().is_some_and(|last| last.starts_with("block")) {
             parts.pop(); // .block[..]
         }
 
@@ -276,11 +407,15 @@ pub fn process_java_file(path: &Path) -> io::Result<BlockInfo> {
 
     let binfo = BlockInfo {
         path: path.to_path_buf(),
-        package: package,
-        classname: classname,
-        ids: ids,
-        target: target,
-        target_new: target_new,
+        package,
+        classname,
+        ids,
+        options,
+        language,
+        target,
+        target_classname,
+        target_language,
+        target_new,
         target_next_to: next_to,
     };
 
@@ -303,11 +438,27 @@ fn java_package_find(line: &str, out: &mut Option<String>) -> Option<()> {
     Some(())
 }
 
-static WHITESPACE: &'static str = " \t\n\r";
+fn kotlin_package_find(line: &str, out: &mut Option<String>) -> Option<()> {
+    let marker = "package ";
+
+    if !line.trim_start().starts_with(marker) {
+        return None;
+    }
+
+    let start = line.find(marker)? + marker.len();
+    let end = line.find(";").unwrap_or(line.len());
+
+    let package = &line[start..end].trim();
+
+    out.replace(package.to_string());
+    Some(())
+}
+
+static WHITESPACE: &str = " \t\n\r";
 
 fn first_non_ws(line: &str) -> Option<usize> {
     for (i, b) in line.bytes().enumerate() {
-        if !WHITESPACE.as_bytes().iter().any(|w| *w == b) {
+        if !WHITESPACE.as_bytes().contains(&b) {
             return Some(i);
         }
     }
@@ -317,7 +468,7 @@ fn first_non_ws(line: &str) -> Option<usize> {
 
 fn first_ws(line: &str) -> Option<usize> {
     for (i, b) in line.bytes().enumerate() {
-        if WHITESPACE.as_bytes().iter().any(|w| *w == b) {
+        if WHITESPACE.as_bytes().contains(&b) {
             return Some(i);
         }
     }
@@ -343,14 +494,266 @@ fn java_classname_find(line: &str, out: &mut Option<String>) -> Option<()> {
     Some(())
 }
 
-fn java_line_processors() -> Vec<Box<Fn(&str) -> Option<Vec<String>>>> {
+// `class CrusherBlock(props: Properties) : Block(props)` or
+// `object Blocks {`.
+fn kotlin_classname_find(line: &str, out: &mut Option<String>) -> Option<()> {
+    let start = ["class ", "object "].iter()
+        .filter_map(|marker| line.find(marker).map(|i| i + marker.len()))
+        .min()?;
+
+    let start = first_non_ws(&line[start..])? + start;
+
+    let end = line[start..]
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(line.len() - start) + start;
+
+    if end <= start {
+        return None;
+    }
+
+    out.replace(line[start..end].to_string());
+    Some(())
+}
+
+type LineProcessor = Box<dyn Fn(&str) -> Option<Vec<String>>>;
+
+fn java_line_processors() -> Vec<LineProcessor> {
     vec![
         Box::new(java_field_ids),
         Box::new(java_field_id),
         Box::new(java_comment_ids),
+        Box::new(java_annotation_ids),
     ]
 }
 
+fn kotlin_line_processors() -> Vec<LineProcessor> {
+    vec![
+        Box::new(kotlin_field_ids),
+        // `const val VSC_BLOCK_ID = "crusher"` reads just like java.
+        Box::new(java_field_id),
+        Box::new(java_comment_ids),
+        Box::new(java_annotation_ids),
+    ]
+}
+
+// `val VSC_BLOCK_IDS = arrayOf("crusher", "crusher_on")`
+fn kotlin_field_ids(line: &str) -> Option<Vec<String>> {
+    if !line.contains("VSC_BLOCK_IDS") {
+        return None;
+    }
+
+    let beg = line.find("=")?;
+
+    read_quoted_ids(&line[beg..])
+}
+
+static ANNOTATION: &str = "@VoxelShapeSource";
+
+// Split the parameters of `@VoxelShapeSource(...)` into (name, value)
+// pairs. A value without a name is the annotation's `value`. Arrays may
+// be written as `{..}` (java) or `[..]` (kotlin). An annotation over
+// several lines is joined into one first, see `join_annotation`.
+fn java_annotation_params(line: &str) -> Option<Vec<(String, String)>> {
+    let beg = line.find(ANNOTATION)? + ANNOTATION.len();
+    let rest = &line[beg..];
+
+    if !rest.trim_start().starts_with('(') {
+        return None;
+    }
+
+    let beg = rest.find('(')? + 1;
+    let end = rest.rfind(')')?;
+
+    if end < beg {
+        return None;
+    }
+
+    let mut params = Vec::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut start = beg;
+
+    for (i, c) in rest[..end].char_indices().skip_while(|(i, _)| *i < beg) {
+        match c {
+            '"' => quoted = !quoted,
+            '{' | '[' if !quoted => depth += 1,
+            '}' | ']' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                params.push(&rest[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+
+    params.push(&rest[start..end]);
+
+    let params = params.iter()
+        .map(|param| param.trim())
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let eq = param.find('=');
+            let quote = param.find('"');
+
+            match eq {
+                Some(eq) if quote.is_none_or(|quote| eq < quote) =>
+                    (param[..eq].trim().to_string(), param[eq + 1..].trim().to_string()),
+                _ => ("value".to_string(), param.to_string()),
+            }
+        })
+        .collect();
+
+    Some(params)
+}
+
+// Join the lines of a `@VoxelShapeSource(...)` spread over several lines,
+// so it's read like a single line. Nothing is returned until the closing
+// parenthesis is found.
+fn join_annotation(pending: &mut Option<String>, line: String) -> Option<String> {
+    let line = match pending.take() {
+        Some(start) => start + " " + line.trim(),
+        None => line,
+    };
+
+    if annotation_open(&line) {
+        *pending = Some(line);
+        None
+    } else {
+        Some(line)
+    }
+}
+
+fn annotation_open(line: &str) -> bool {
+    let rest = match line.find(ANNOTATION) {
+        Some(beg) => line[beg + ANNOTATION.len()..].trim_start(),
+        None => return false,
+    };
+
+    if !rest.starts_with('(') {
+        return false;
+    }
+
+    let mut depth = 0;
+    let mut quoted = false;
+
+    for c in rest.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return false;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    true
+}
+
+fn java_annotation_ids(line: &str) -> Option<Vec<String>> {
+    java_annotation_params(line)?.iter()
+        .find(|(name, _)| name == "value")
+        .and_then(|(_, value)| read_quoted_ids(value))
+}
+
+fn java_annotation_options(line: &str, options: &mut BlockOptions) -> Option<()> {
+    for (name, value) in java_annotation_params(line)? {
+        // Numbers aren't quoted.
+        if name == "max_boxes" {
+            match value.parse() {
+                Ok(max_boxes) => options.max_boxes = Some(max_boxes),
+                Err(_) => eprintln!("Invalid max_boxes in {}: {}", ANNOTATION, value),
+            }
+
+            continue;
+        }
+
+        // A bad parameter is reported, the others still count.
+        let mut strings = match read_quoted_ids(&value) {
+            Some(strings) => strings,
+            None => {
+                eprintln!("Invalid {} in {}: {}", name, ANNOTATION, value);
+                continue;
+            },
+        };
+
+        match name.as_str() {
+            "output" => options.output = strings.pop(),
+            "mode" => match strings.pop().as_deref().and_then(MergeMode::from) {
+                Some(mode) => options.merge = Some(mode),
+                None => eprintln!("Unknown merge mode in {}: {}", ANNOTATION, value),
+            },
+            "exclude" => options.skip_variants.append(&mut strings),
+            "shape" => match strings.pop().as_deref().and_then(ShapeSource::from) {
+                Some(shape) => options.shape = Some(shape),
+                None => eprintln!("Unknown shape in {}: {}", ANNOTATION, value),
+            },
+            "bounds" => match strings.pop().as_deref().and_then(BoundsPolicy::from) {
+                Some(bounds) => options.bounds = Some(bounds),
+                None => eprintln!("Unknown bounds policy in {}: {}", ANNOTATION, value),
+            },
+            "flat" => match strings.pop().as_deref().and_then(FlatPolicy::from) {
+                Some(flat) => options.flat = Some(flat),
+                None => eprintln!("Unknown flat policy in {}: {}", ANNOTATION, value),
+            },
+            _ => {},
+        }
+    }
+
+    Some(())
+}
+
+// Per-block directives: `// VSC! MERGE exact`, `// VSC! SKIP_VARIANT waterlogged=true`,
+// `// VSC! SHAPE full_cube`, `// VSC! OUTPUT MyShapes`, `// VSC! BOUNDS split`,
+// `// VSC! FLAT thicken` and `// VSC! MAX_BOXES 8`.
+fn java_comment_options(line: &str, options: &mut BlockOptions) -> Option<()> {
+    let marker = "VSC! ";
+
+    let beg = line.find(marker)? + marker.len();
+    let rest = line[beg..].trim_start();
+
+    // The value is the rest of the line, e.g. "facing=north, powered=true".
+    let end = rest.find(char::is_whitespace)?;
+    let directive = &rest[..end];
+    let value = rest[end..].trim().trim_matches('"');
+
+    if value.is_empty() {
+        return None;
+    }
+
+    match directive {
+        "MERGE" => match MergeMode::from(value) {
+            Some(mode) => options.merge = Some(mode),
+            None => eprintln!("Unknown merge mode in VSC! MERGE: {}", value),
+        },
+        "SKIP_VARIANT" => options.skip_variants.push(value.to_string()),
+        "SHAPE" => match ShapeSource::from(value) {
+            Some(shape) => options.shape = Some(shape),
+            None => eprintln!("Unknown shape in VSC! SHAPE: {}", value),
+        },
+        "OUTPUT" => options.output = Some(value.to_string()),
+        "MAX_BOXES" => match value.parse() {
+            Ok(max_boxes) => options.max_boxes = Some(max_boxes),
+            Err(_) => eprintln!("Invalid number in VSC! MAX_BOXES: {}", value),
+        },
+        "BOUNDS" => match BoundsPolicy::from(value) {
+            Some(bounds) => options.bounds = Some(bounds),
+            None => eprintln!("Unknown bounds policy in VSC! BOUNDS: {}", value),
+        },
+        "FLAT" => match FlatPolicy::from(value) {
+            Some(flat) => options.flat = Some(flat),
+            None => eprintln!("Unknown flat policy in VSC! FLAT: {}", value),
+        },
+        _ => {},
+    }
+
+    Some(())
+}
+
 fn java_comment_ids(line: &str) -> Option<Vec<String>> {
     let marker = "VSC! BLOCK_ID";
 
@@ -428,3 +831,270 @@ fn read_quoted_ids(line: &str) -> Option<Vec<String>> {
         }
     }
 }
+
+// Classes which are constructed in registration code but never are the
+// registered block.
+static NOT_BLOCKS: &[&str] = &[
+    "Identifier",
+    "ResourceLocation",
+    "ModelResourceLocation",
+    "Properties",
+    "Settings",
+    "BlockItem",
+];
+
+/// Find block registrations in java sources and map the registered
+/// classes to their registry names (classname -> ids).
+///
+/// Recognised are statements like
+/// `Registry.register(Registry.BLOCK, new Identifier("mymod", "crusher"), new CrusherBlock(..))`,
+/// `BLOCKS.register("crusher", () -> new CrusherBlock(..))`,
+/// `new CrusherBlock(..).setRegistryName("crusher")` and a bare
+/// `setRegistryName("crusher")` in the block's own constructor.
+pub fn infer_registered_ids(paths: &[&PathBuf]) -> HashMap<String, Vec<String>> {
+    let mut registered: HashMap<String, Vec<String>> = HashMap::new();
+
+    // In the files' order, so the ids come out in the same order as
+    // when reading them one by one.
+    for (class, id) in parallel::map(paths, |path| java_registrations(path)).into_iter().flatten() {
+        let ids = registered.entry(class).or_default();
+
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    registered
+}
+
+// The (classname, id) registrations in a java source.
+fn java_registrations(path: &Path) -> Vec<(String, String)> {
+    let mut registrations = Vec::new();
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(_) => return registrations,
+    };
+
+    let mut own_classname = None;
+    let mut statement = String::new();
+
+    for line in source.lines() {
+        if own_classname.is_none() {
+            java_classname_find(line, &mut own_classname);
+        }
+
+        // Drop line comments, statements may span multiple lines.
+        let line = line.find("//").map_or(line, |i| &line[..i]);
+        statement.push_str(line);
+        statement.push(' ');
+
+        if !line.contains(';') {
+            continue;
+        }
+
+        if let Some((class, id)) = java_registration(&statement) {
+            if let Some(class) = class.or(own_classname.clone()) {
+                registrations.push((class, id));
+            }
+        }
+
+        statement.clear();
+    }
+
+    registrations
+}
+
+// Find (constructed class, registry name) in a single statement.
+fn java_registration(statement: &str) -> Option<(Option<String>, String)> {
+    let class = java_constructed_class(statement);
+
+    // Block items are registered under the same name as their block.
+    if java_registers_item(statement, class.is_some()) {
+        return None;
+    }
+
+    let id = java_registry_name(statement)?;
+
+    if class.is_none() && !statement.contains("setRegistryName") {
+        return None;
+    }
+
+    Some((class, id))
+}
+
+fn java_registry_name(statement: &str) -> Option<String> {
+    let markers = [
+        "new Identifier(",
+        "new ResourceLocation(",
+        "setRegistryName(",
+        ".register(",
+    ];
+
+    for marker in markers.iter() {
+        let beg = match statement.find(marker) {
+            Some(beg) => beg + marker.len(),
+            None => continue,
+        };
+
+        let args = &statement[beg..];
+
+        // `BLOCKS.register(NAME, ...)` uses a constant, not a literal.
+        if *marker == ".register(" && !args.trim_start().starts_with('"') {
+            continue;
+        }
+
+        let end = args.find(')').unwrap_or(args.len());
+        let ids = read_quoted_ids(&args[..end])?;
+
+        // ("mymod", "crusher") and ("mymod:crusher") are both
+        // "mymod:crusher", a lone ("crusher") has no namespace.
+        match ids.as_slice() {
+            [namespace, path] => return Some(format!("{}:{}", namespace, path)),
+            [id] => return Some(id.to_string()),
+            _ => {},
+        }
+    }
+
+    None
+}
+
+// Whether the statement registers an item: `Registry.register(Registry.ITEM, ..)`,
+// `ITEMS.register(..)` or `new BlockItem(..).setRegistryName(..)`.
+fn java_registers_item(statement: &str, constructs_block: bool) -> bool {
+    let last_segment = |name: &str| name.rsplit('.').next().unwrap_or(name).trim().to_string();
+
+    for (i, _) in statement.match_indices("register(") {
+        let args = &statement[i + "register(".len()..];
+        let registry = args.split([',', ')']).next().unwrap_or("");
+
+        if last_segment(registry) == "ITEM" {
+            return true;
+        }
+
+        let receiver = statement[..i].strip_suffix('.')
+            .map(|before| before.rsplit(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).next().unwrap_or(""))
+            .unwrap_or("");
+
+        if last_segment(receiver) == "ITEMS" {
+            return true;
+        }
+    }
+
+    !constructs_block && constructed_classes(statement).iter().any(|name| name.ends_with("Item"))
+}
+
+fn java_constructed_class(statement: &str) -> Option<String> {
+    constructed_classes(statement).into_iter()
+        .find(|name| !NOT_BLOCKS.contains(name) && !name.ends_with("Item"))
+        .map(|name| name.to_string())
+}
+
+// Classes constructed with `new Foo(..)` or `Foo::new`, without their
+// package or outer class.
+fn constructed_classes(statement: &str) -> Vec<&str> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+
+    let mut classes = Vec::new();
+
+    for (i, _) in statement.match_indices("new ") {
+        let rest = &statement[i + 4..];
+        let name = rest.split(|c: char| !is_ident(c)).next().unwrap_or("");
+
+        if rest[name.len()..].starts_with('(') {
+            classes.push(name);
+        }
+    }
+
+    for (i, _) in statement.match_indices("::new") {
+        let name = statement[..i].rsplit(|c: char| !is_ident(c)).next().unwrap_or("");
+        classes.push(name);
+    }
+
+    classes.into_iter()
+        // Strip the package/outer class, `new foo.bar.CrusherBlock(..)`.
+        .map(|name| name.rsplit('.').next().unwrap_or(name))
+        .filter(|name| name.starts_with(|c: char| c.is_ascii_uppercase()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::java_registration as registration;
+    use super::{ java_comment_options, BlockOptions };
+
+    fn block(class: &str, id: &str) -> Option<(Option<String>, String)> {
+        Some((Some(class.to_string()), id.to_string()))
+    }
+
+    #[test]
+    fn fabric_registry() {
+        assert_eq!(
+            registration(r#"Registry.register(Registry.BLOCK, new Identifier("mymod", "crusher"), new CrusherBlock(SETTINGS));"#),
+            block("CrusherBlock", "mymod:crusher"));
+
+        assert_eq!(
+            registration(r#"Registry.register(Registries.BLOCK, new Identifier("mymod:crusher"), new CrusherBlock(SETTINGS));"#),
+            block("CrusherBlock", "mymod:crusher"));
+    }
+
+    #[test]
+    fn fabric_items_are_skipped() {
+        assert_eq!(
+            registration(r#"Registry.register(Registry.ITEM, new Identifier("mymod", "crusher"), new BlockItem(CRUSHER, SETTINGS));"#),
+            None);
+
+        assert_eq!(
+            registration(r#"Registry.register(BuiltInRegistries.ITEM, new ResourceLocation("mymod:crusher"), new CrusherBlock.Item(CRUSHER));"#),
+            None);
+    }
+
+    #[test]
+    fn deferred_register() {
+        assert_eq!(
+            registration(r#"CRUSHER = BLOCKS.register("crusher", () -> new CrusherBlock(PROPS.tab(ITEM_GROUP)));"#),
+            block("CrusherBlock", "crusher"));
+
+        assert_eq!(
+            registration(r#"CRUSHER = BLOCK_ITEMS_AND_BLOCKS.register("crusher", CrusherBlock::new);"#),
+            block("CrusherBlock", "crusher"));
+
+        assert_eq!(
+            registration(r#"CRUSHER_ITEM = ITEMS.register("crusher", () -> new BlockItem(CRUSHER.get(), PROPS));"#),
+            None);
+    }
+
+    #[test]
+    fn set_registry_name() {
+        assert_eq!(
+            registration(r#"event.getRegistry().register(new CrusherBlock(PROPS).setRegistryName("mymod", "crusher"));"#),
+            block("CrusherBlock", "mymod:crusher"));
+
+        // In the block's own constructor, the class is the file's.
+        assert_eq!(
+            registration(r#"setRegistryName(new ResourceLocation("mymod:crusher"));"#),
+            Some((None, "mymod:crusher".to_string())));
+
+        assert_eq!(
+            registration(r#"event.getRegistry().register(new BlockItem(CRUSHER, PROPS).setRegistryName("crusher"));"#),
+            None);
+    }
+
+    #[test]
+    fn without_a_literal_name() {
+        assert_eq!(registration(r#"CRUSHER = BLOCKS.register(NAME, CrusherBlock::new);"#), None);
+    }
+
+    #[test]
+    fn directive_values_run_to_the_end_of_the_line() {
+        let mut options = BlockOptions::default();
+
+        java_comment_options("    // VSC! SKIP_VARIANT facing=north, powered=true", &mut options);
+        java_comment_options("    // VSC! OUTPUT \"MyShapes\" ", &mut options);
+        java_comment_options("    // VSC! MERGE exact please", &mut options);
+
+        assert_eq!(options.skip_variants, vec!["facing=north, powered=true"]);
+        assert_eq!(options.output.as_deref(), Some("MyShapes"));
+        assert!(options.merge.is_none());
+    }
+}
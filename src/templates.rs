@@ -0,0 +1,141 @@
+/// The geometry of common vanilla parent models, so models inheriting from
+/// them work without the game's assets. Only the element boxes are here,
+/// no textures or faces. Anything else can be read from the client's
+/// assets, see `client_assets` in config.rs.
+static TEMPLATES: &[(&str, &str)] = &[
+    // The roots, they only set up the display
+    ("block/block", "{}"),
+    ("block/thin_block", "{}"),
+
+    // Full blocks
+    ("block/cube", FULL_BLOCK),
+    ("block/cube_all", FULL_BLOCK),
+    ("block/cube_bottom_top", FULL_BLOCK),
+    ("block/cube_column", FULL_BLOCK),
+    ("block/cube_column_horizontal", FULL_BLOCK),
+    ("block/cube_directional", FULL_BLOCK),
+    ("block/cube_mirrored_all", FULL_BLOCK),
+    ("block/cube_top", FULL_BLOCK),
+    ("block/leaves", FULL_BLOCK),
+    ("block/orientable", FULL_BLOCK),
+    ("block/orientable_vertical", FULL_BLOCK),
+    ("block/orientable_with_bottom", FULL_BLOCK),
+
+    // Slabs and stairs
+    ("block/slab", r#"{ "elements": [
+        { "from": [0, 0, 0], "to": [16, 8, 16] } ] }"#),
+    ("block/slab_top", r#"{ "elements": [
+        { "from": [0, 8, 0], "to": [16, 16, 16] } ] }"#),
+    ("block/stairs", r#"{ "elements": [
+        { "from": [0, 0, 0], "to": [16, 8, 16] },
+        { "from": [8, 8, 0], "to": [16, 16, 16] } ] }"#),
+    ("block/inner_stairs", r#"{ "elements": [
+        { "from": [0, 0, 0], "to": [16, 8, 16] },
+        { "from": [8, 8, 0], "to": [16, 16, 16] },
+        { "from": [0, 8, 8], "to": [8, 16, 16] } ] }"#),
+    ("block/outer_stairs", r#"{ "elements": [
+        { "from": [0, 0, 0], "to": [16, 8, 16] },
+        { "from": [8, 8, 8], "to": [16, 16, 16] } ] }"#),
+
+    // Fences, fence gates, walls and panes
+    ("block/fence_post", r#"{ "elements": [
+        { "from": [6, 0, 6], "to": [10, 16, 10] } ] }"#),
+    ("block/fence_side", r#"{ "elements": [
+        { "from": [7, 12, 0], "to": [9, 15, 9] },
+        { "from": [7, 6, 0], "to": [9, 9, 9] } ] }"#),
+    ("block/template_fence_gate", r#"{ "elements": [
+        { "from": [0, 5, 7], "to": [2, 16, 9] },
+        { "from": [14, 5, 7], "to": [16, 16, 9] },
+        { "from": [6, 6, 7], "to": [8, 15, 9] },
+        { "from": [8, 6, 7], "to": [10, 15, 9] },
+        { "from": [2, 6, 7], "to": [6, 9, 9] },
+        { "from": [2, 12, 7], "to": [6, 15, 9] },
+        { "from": [10, 6, 7], "to": [14, 9, 9] },
+        { "from": [10, 12, 7], "to": [14, 15, 9] } ] }"#),
+    ("block/template_fence_gate_open", r#"{ "elements": [
+        { "from": [0, 5, 7], "to": [2, 16, 9] },
+        { "from": [14, 5, 7], "to": [16, 16, 9] },
+        { "from": [0, 6, 13], "to": [2, 15, 15] },
+        { "from": [14, 6, 13], "to": [16, 15, 15] },
+        { "from": [0, 6, 9], "to": [2, 9, 13] },
+        { "from": [0, 12, 9], "to": [2, 15, 13] },
+        { "from": [14, 6, 9], "to": [16, 9, 13] },
+        { "from": [14, 12, 9], "to": [16, 15, 13] } ] }"#),
+    ("block/template_wall_post", r#"{ "elements": [
+        { "from": [4, 0, 4], "to": [12, 16, 12] } ] }"#),
+    ("block/template_wall_side", r#"{ "elements": [
+        { "from": [5, 0, 0], "to": [11, 14, 8] } ] }"#),
+    ("block/template_wall_side_tall", r#"{ "elements": [
+        { "from": [5, 0, 0], "to": [11, 16, 8] } ] }"#),
+    ("block/template_glass_pane_post", r#"{ "elements": [
+        { "from": [7, 0, 7], "to": [9, 16, 9] } ] }"#),
+    ("block/template_glass_pane_side", r#"{ "elements": [
+        { "from": [7, 0, 0], "to": [9, 16, 7] } ] }"#),
+    ("block/template_glass_pane_side_alt", r#"{ "elements": [
+        { "from": [7, 0, 9], "to": [9, 16, 16] } ] }"#),
+
+    // Doors and trapdoors
+    ("block/door_bottom", DOOR),
+    ("block/door_bottom_rh", DOOR),
+    ("block/door_top", DOOR),
+    ("block/door_top_rh", DOOR),
+    ("block/template_trapdoor_bottom", r#"{ "elements": [
+        { "from": [0, 0, 0], "to": [16, 3, 16] } ] }"#),
+    ("block/template_trapdoor_top", r#"{ "elements": [
+        { "from": [0, 13, 0], "to": [16, 16, 16] } ] }"#),
+    ("block/template_trapdoor_open", r#"{ "elements": [
+        { "from": [0, 0, 13], "to": [16, 16, 16] } ] }"#),
+
+    // Thin blocks
+    ("block/button", r#"{ "elements": [
+        { "from": [5, 0, 6], "to": [11, 2, 10] } ] }"#),
+    ("block/button_pressed", r#"{ "elements": [
+        { "from": [5, 0, 6], "to": [11, 1, 10] } ] }"#),
+    ("block/pressure_plate_up", r#"{ "elements": [
+        { "from": [1, 0, 1], "to": [15, 1, 15] } ] }"#),
+    ("block/pressure_plate_down", r#"{ "elements": [
+        { "from": [1, 0, 1], "to": [15, 0.5, 15] } ] }"#),
+    ("block/carpet", r#"{ "elements": [
+        { "from": [0, 0, 0], "to": [16, 1, 16] } ] }"#),
+
+    // Plants, torches and lanterns
+    ("block/cross", CROSS),
+    ("block/tinted_cross", CROSS),
+    ("block/crop", r#"{ "elements": [
+        { "from": [4, -1, 0], "to": [4, 15, 16] },
+        { "from": [12, -1, 0], "to": [12, 15, 16] },
+        { "from": [0, -1, 4], "to": [16, 15, 4] },
+        { "from": [0, -1, 12], "to": [16, 15, 12] } ] }"#),
+    ("block/template_torch", r#"{ "elements": [
+        { "from": [7, 0, 7], "to": [9, 10, 9] } ] }"#),
+    ("block/template_lantern", r#"{ "elements": [
+        { "from": [5, 0, 5], "to": [11, 7, 11] },
+        { "from": [6, 7, 6], "to": [10, 9, 10] } ] }"#),
+    ("block/template_hanging_lantern", r#"{ "elements": [
+        { "from": [5, 1, 5], "to": [11, 8, 11] },
+        { "from": [6, 8, 6], "to": [10, 10, 10] } ] }"#),
+];
+
+static FULL_BLOCK: &str = r#"{ "elements": [
+    { "from": [0, 0, 0], "to": [16, 16, 16] } ] }"#;
+
+static DOOR: &str = r#"{ "elements": [
+    { "from": [0, 0, 0], "to": [3, 16, 16] } ] }"#;
+
+static CROSS: &str = r#"{ "elements": [
+    { "from": [0.8, 0, 8], "to": [15.2, 16, 8],
+      "rotation": { "origin": [8, 8, 8], "axis": "y", "angle": 45, "rescale": true } },
+    { "from": [8, 0, 0.8], "to": [8, 16, 15.2],
+      "rotation": { "origin": [8, 8, 8], "axis": "y", "angle": 45, "rescale": true } } ] }"#;
+
+/// A template by its path in a resource pack, e.g.
+/// "assets/minecraft/models/block/slab.json".
+pub fn get(asset_path: &str) -> Option<&'static str> {
+    let name = asset_path
+        .strip_prefix("assets/minecraft/models/")?
+        .strip_suffix(".json")?;
+
+    TEMPLATES.iter()
+        .find(|(id, _)| *id == name)
+        .map(|(_, json)| *json)
+}
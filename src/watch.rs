@@ -0,0 +1,88 @@
+use crate::{ automatic, Settings };
+use crate::cache;
+use crate::scan;
+
+use std::collections::{ HashMap, HashSet };
+use std::path::{ Path, PathBuf };
+use std::thread;
+use std::time::{ Duration, SystemTime };
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Modification time and size of every file that may affect the shapes.
+type Snapshot = HashMap<PathBuf, (SystemTime, u64)>;
+
+/// Generate the shapes whenever the project's files change, until killed.
+/// Only the classes whose inputs changed are generated again, see
+/// cache.rs.
+pub fn run(settings: &Settings) -> Result<(), String> {
+    let mut last: Option<Snapshot> = None;
+
+    // What the runs write themselves, which isn't a change to the project.
+    let mut outputs = HashSet::new();
+    outputs.insert(settings.project_dir.join(cache::CACHE_FILE));
+
+    println!("Watching {:?}, press Ctrl+C to stop.", settings.project_dir);
+
+    loop {
+        // E.g. a directory removed while it was read, tried again on the
+        // next poll.
+        let mut current = match snapshot(&settings.project_dir, &outputs) {
+            Ok(current) => current,
+            Err(e) => {
+                eprintln!("Err: {:?}", e);
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            },
+        };
+
+        if let Some(last) = last.as_ref() {
+            let changed = current.iter()
+                .filter(|(path, stamp)| last.get(*path) != Some(stamp))
+                .count();
+
+            let removed = last.keys()
+                .filter(|path| !current.contains_key(*path))
+                .count();
+
+            if changed + removed == 0 {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+
+            println!("{} files changed, {} removed.", changed, removed);
+        }
+
+        // A failed run is retried once the files change again.
+        match automatic(settings) {
+            Ok(targets) => outputs.extend(targets),
+            Err(e) => eprintln!("Err: {:?}", e),
+        }
+
+        // Taken before generating, so whatever changes during the run is
+        // generated on the next poll.
+        current.retain(|path, _| !outputs.contains(path));
+        last = Some(current);
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn snapshot(project_dir: &Path, outputs: &HashSet<PathBuf>) -> Result<Snapshot, String> {
+    let mut paths = scan::discover_files(project_dir)?;
+
+    // The config and overrides files live in the project directory itself.
+    if let Ok(ents) = project_dir.read_dir() {
+        paths.extend(ents.flatten()
+                     .map(|ent| ent.path())
+                     .filter(|path| path.is_file()));
+    }
+
+    Ok(paths.into_iter()
+       .filter(|path| !outputs.contains(path))
+       .filter_map(|path| {
+           let meta = path.metadata().ok()?;
+           Some((path, (meta.modified().ok()?, meta.len())))
+       })
+       .collect())
+}
@@ -0,0 +1,225 @@
+// Pins the blockstate and element rotation semantics to the game's, using
+// vanilla blocks whose shapes are known. The models in tests/vanilla only
+// contain the element boxes of the vanilla models (parents inlined).
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::process::Command;
+
+type Boxes = Vec<[f32; 6]>;
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+
+    for ent in fs::read_dir(from).unwrap() {
+        let path = ent.unwrap().path();
+        let target = to.join(path.file_name().unwrap());
+
+        if path.is_dir() {
+            copy_dir(&path, &target);
+        } else {
+            fs::copy(&path, &target).unwrap();
+        }
+    }
+}
+
+// Generate the shapes of a single vanilla block, without merging so every
+// element can be checked and without snapping so 90 degree rotations have
+// to be exact. Returns the boxes by variant key.
+fn generate(block_id: &str) -> HashMap<String, Boxes> {
+    let project = std::env::temp_dir()
+        .join(format!("vsc-vanilla-{}-{}", block_id, std::process::id()));
+
+    let _ = fs::remove_dir_all(&project);
+
+    let resources = project.join("src/main/resources");
+    copy_dir(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/vanilla"), &resources);
+
+    let java = project.join("src/main/java/vanilla");
+    fs::create_dir_all(&java).unwrap();
+
+    fs::write(
+        java.join("VanillaBlock.java"),
+        format!("package vanilla;\n\n// VSC! BLOCK_ID \"{}\"\npublic class VanillaBlock {{\n}}\n", block_id),
+    ).unwrap();
+
+    fs::write(
+        project.join("voxelshape-conv.json"),
+        "{ \"merge\": \"none\", \"snap\": { \"grid\": null } }",
+    ).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_voxelshape-conv"))
+        .arg(&project)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let generated = fs::read_to_string(java.join("VanillaBlockBB.java")).unwrap();
+    let _ = fs::remove_dir_all(&project);
+
+    parse_shapes(&generated)
+}
+
+fn parse_shapes(java: &str) -> HashMap<String, Boxes> {
+    let mut shapes = HashMap::new();
+    let mut current: Option<(String, Boxes)> = None;
+
+    for line in java.lines() {
+        if line.contains("Util.make(") {
+            let comment = line.split("// ").nth(1).unwrap_or("");
+            let key = comment.split(" (").next().unwrap().trim().to_string();
+            current = Some((key, Vec::new()));
+        }
+
+        for (i, _) in line.match_indices("makeCuboidShape(") {
+            let args = &line[i + "makeCuboidShape(".len()..];
+            let args = &args[..args.find(')').unwrap()];
+
+            let v = args.split(',')
+                .map(|v| v.trim().parse::<f32>().unwrap())
+                .collect::<Vec<f32>>();
+
+            current.as_mut().unwrap().1.push([v[0], v[1], v[2], v[3], v[4], v[5]]);
+        }
+
+        if line.contains("return part;") {
+            let (key, boxes) = current.take().unwrap();
+            shapes.insert(key, boxes);
+        }
+    }
+
+    shapes
+}
+
+fn assert_shape(shapes: &HashMap<String, Boxes>, key: &str, expected: &[[f32; 6]]) {
+    let sorted = |boxes: &[[f32; 6]]| {
+        let mut boxes = boxes.to_vec();
+        boxes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        boxes
+    };
+
+    let actual = shapes.get(key).unwrap_or_else(|| panic!("No shape for {}", key));
+    let (actual, expected) = (sorted(actual), sorted(expected));
+
+    // Non-90 degree rotations go through f32 trig.
+    let close = actual.len() == expected.len() &&
+        actual.iter().zip(expected.iter())
+        .all(|(a, b)| a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 0.0001));
+
+    assert!(close, "{}: {:?} != {:?}", key, actual, expected);
+}
+
+#[test]
+fn stairs() {
+    let shapes = generate("oak_stairs");
+    let slab = [0.0, 0.0, 0.0, 16.0, 8.0, 16.0];
+    let top_slab = [0.0, 8.0, 0.0, 16.0, 16.0, 16.0];
+
+    assert_shape(&shapes, "facing=east,half=bottom,shape=straight",
+                 &[slab, [8.0, 8.0, 0.0, 16.0, 16.0, 16.0]]);
+    assert_shape(&shapes, "facing=west,half=bottom,shape=straight",
+                 &[slab, [0.0, 8.0, 0.0, 8.0, 16.0, 16.0]]);
+    assert_shape(&shapes, "facing=south,half=bottom,shape=straight",
+                 &[slab, [0.0, 8.0, 8.0, 16.0, 16.0, 16.0]]);
+    assert_shape(&shapes, "facing=north,half=bottom,shape=straight",
+                 &[slab, [0.0, 8.0, 0.0, 16.0, 16.0, 8.0]]);
+    assert_shape(&shapes, "facing=east,half=top,shape=straight",
+                 &[top_slab, [8.0, 0.0, 0.0, 16.0, 8.0, 16.0]]);
+    assert_shape(&shapes, "facing=south,half=top,shape=straight",
+                 &[top_slab, [0.0, 0.0, 8.0, 16.0, 8.0, 16.0]]);
+    assert_shape(&shapes, "facing=east,half=bottom,shape=outer_right",
+                 &[slab, [8.0, 8.0, 8.0, 16.0, 16.0, 16.0]]);
+    assert_shape(&shapes, "facing=east,half=top,shape=outer_right",
+                 &[top_slab, [8.0, 0.0, 8.0, 16.0, 8.0, 16.0]]);
+    assert_shape(&shapes, "facing=east,half=bottom,shape=inner_left",
+                 &[slab, [0.0, 8.0, 0.0, 16.0, 16.0, 8.0], [8.0, 8.0, 8.0, 16.0, 16.0, 16.0]]);
+}
+
+#[test]
+fn hopper() {
+    let shapes = generate("hopper");
+
+    let bowl_bottom = [0.0, 10.0, 0.0, 16.0, 11.0, 16.0];
+    let middle = [4.0, 4.0, 4.0, 12.0, 10.0, 12.0];
+
+    assert_shape(&shapes, "facing=down", &[
+        bowl_bottom,
+        [0.0, 11.0, 0.0, 2.0, 16.0, 16.0],
+        [14.0, 11.0, 0.0, 16.0, 16.0, 16.0],
+        [2.0, 11.0, 0.0, 14.0, 16.0, 2.0],
+        [2.0, 11.0, 14.0, 14.0, 16.0, 16.0],
+        middle,
+        [6.0, 0.0, 6.0, 10.0, 4.0, 10.0],
+    ]);
+
+    assert_shape(&shapes, "facing=north", &[
+        bowl_bottom,
+        [0.0, 11.0, 0.0, 2.0, 16.0, 16.0],
+        [14.0, 11.0, 0.0, 16.0, 16.0, 16.0],
+        [2.0, 11.0, 0.0, 14.0, 16.0, 2.0],
+        [2.0, 11.0, 14.0, 14.0, 16.0, 16.0],
+        middle,
+        [6.0, 4.0, 0.0, 10.0, 8.0, 4.0],
+    ]);
+
+    assert_shape(&shapes, "facing=east", &[
+        bowl_bottom,
+        [0.0, 11.0, 0.0, 16.0, 16.0, 2.0],
+        [0.0, 11.0, 14.0, 16.0, 16.0, 16.0],
+        [14.0, 11.0, 2.0, 16.0, 16.0, 14.0],
+        [0.0, 11.0, 2.0, 2.0, 16.0, 14.0],
+        middle,
+        [12.0, 4.0, 6.0, 16.0, 8.0, 10.0],
+    ]);
+
+    assert_shape(&shapes, "facing=south", &[
+        bowl_bottom,
+        [14.0, 11.0, 0.0, 16.0, 16.0, 16.0],
+        [0.0, 11.0, 0.0, 2.0, 16.0, 16.0],
+        [2.0, 11.0, 14.0, 14.0, 16.0, 16.0],
+        [2.0, 11.0, 0.0, 14.0, 16.0, 2.0],
+        middle,
+        [6.0, 4.0, 12.0, 10.0, 8.0, 16.0],
+    ]);
+}
+
+#[test]
+fn button() {
+    let shapes = generate("stone_button");
+
+    assert_shape(&shapes, "face=floor,facing=east,powered=false",
+                 &[[6.0, 0.0, 5.0, 10.0, 2.0, 11.0]]);
+    assert_shape(&shapes, "face=wall,facing=north,powered=false",
+                 &[[5.0, 6.0, 14.0, 11.0, 10.0, 16.0]]);
+    assert_shape(&shapes, "face=wall,facing=east,powered=false",
+                 &[[0.0, 6.0, 5.0, 2.0, 10.0, 11.0]]);
+    assert_shape(&shapes, "face=ceiling,facing=north,powered=false",
+                 &[[5.0, 14.0, 6.0, 11.0, 16.0, 10.0]]);
+}
+
+#[test]
+fn full_blocks_stay_exact() {
+    let full = [[0.0, 0.0, 0.0, 16.0, 16.0, 16.0]];
+
+    let logs = generate("oak_log");
+    assert_shape(&logs, "axis=y", &full);
+    assert_shape(&logs, "axis=z", &full);
+    assert_shape(&logs, "axis=x", &full);
+
+    let observers = generate("observer");
+    assert_shape(&observers, "facing=down,powered=false", &full);
+    assert_shape(&observers, "facing=up,powered=false", &full);
+    assert_shape(&observers, "facing=east,powered=false", &full);
+}
+
+#[test]
+fn rescaled_cross() {
+    let shapes = generate("poppy");
+
+    // Both planes span the block's diagonal after being rescaled.
+    let diagonal = [0.8, 0.0, 0.8, 15.2, 16.0, 15.2];
+
+    assert_shape(&shapes, "", &[diagonal, diagonal]);
+}